@@ -1,3 +1,3 @@
-fn main() {
-    gl_object::glfw_example();
+fn main() -> Result<(), gl_object::Error> {
+    gl_object::glfw_example()
 }
@@ -0,0 +1,3 @@
+fn main() -> Result<(), gl_object::Error> {
+    gl_object::scene_example()
+}
@@ -1,7 +1,8 @@
 #[cfg(feature = "sdl2")]
 use gl_object::sdl2_example;
 
-fn main() {
+fn main() -> Result<(), gl_object::Error> {
     #[cfg(feature = "sdl2")]
-    sdl2_example();
+    sdl2_example()?;
+    Ok(())
 }
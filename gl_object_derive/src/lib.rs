@@ -0,0 +1,144 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+/// Implements `gl_object::vertex::Vertex` for a `#[repr(C)]` struct with
+/// named fields, one vertex attribute per field.
+///
+/// Fields are named after the vertex input they feed unless they carry
+/// `#[vertex(name = "in_position")]`; `#[vertex(normalized)]` maps integer
+/// fields to `[0, 1]` or `[-1, 1]`. Every field type must implement
+/// `gl_object::vertex::AttributeFormat`, and the struct must not contain
+/// padding.
+#[proc_macro_derive(Vertex, attributes(vertex))]
+pub fn derive_vertex(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn is_repr_c(input: &DeriveInput) -> bool {
+    let mut repr_c = false;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("repr"))
+    {
+        let _ = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("C") {
+                repr_c = true;
+            }
+            // Skip the arguments of `align(N)` and `packed(N)`
+            if meta.input.peek(syn::token::Paren) {
+                let content;
+                syn::parenthesized!(content in meta.input);
+                content.parse::<TokenStream2>()?;
+            }
+            Ok(())
+        });
+    }
+    repr_c
+}
+
+/// Attribute name and normalization of one field.
+fn field_options(field: &syn::Field) -> syn::Result<(String, bool)> {
+    let mut name = field
+        .ident
+        .as_ref()
+        .map(|ident| ident.to_string())
+        .unwrap_or_default();
+    let mut normalized = false;
+
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("vertex"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                let value: LitStr = meta.value()?.parse()?;
+                name = value.value();
+                Ok(())
+            } else if meta.path.is_ident("normalized") {
+                normalized = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `name = \"...\"` or `normalized`"))
+            }
+        })?;
+    }
+
+    Ok((name, normalized))
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "Vertex cannot be derived for generic structs",
+        ));
+    }
+    if !is_repr_c(input) {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "Vertex structs must be #[repr(C)]",
+        ));
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "Vertex structs must have named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "Vertex can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut attributes = vec![];
+    let mut types = vec![];
+    for field in fields.iter() {
+        let (name, normalized) = field_options(field)?;
+        let field_ident = &field.ident;
+        let field_type = &field.ty;
+        types.push(field_type);
+        attributes.push(quote! {
+            ::gl_object::vertex::VertexAttribute {
+                name: ::std::string::String::from(#name),
+                components: <#field_type as ::gl_object::vertex::AttributeFormat>::COMPONENTS,
+                attribute_type: <#field_type as ::gl_object::vertex::AttributeFormat>::TYPE,
+                normalized: #normalized,
+                offset: ::core::mem::offset_of!(Self, #field_ident) as i32,
+            }
+        });
+    }
+
+    Ok(quote! {
+        const _: () = ::core::assert!(
+            ::core::mem::size_of::<#ident>() == 0 #(+ ::core::mem::size_of::<#types>())*,
+            "Vertex structs cannot contain padding"
+        );
+
+        unsafe impl ::gl_object::vertex::Vertex for #ident {
+            fn layout() -> ::gl_object::vertex::VertexBufferLayout {
+                ::gl_object::vertex::VertexBufferLayout {
+                    stride: ::core::mem::size_of::<Self>() as i32,
+                    attributes: ::std::vec![#(#attributes),*],
+                    divisor: 0,
+                }
+            }
+        }
+    })
+}
@@ -1,17 +1,22 @@
 #shader vertex
 in vec2 in_position;
 out vec2 position;
+
+uniform mat4 u_model;
+
 void main() {
   position = in_position;
-  gl_Position = vec4(in_position - 0.5, 0.0, 1.0);
+#ifdef SHIFTED
+  gl_Position = u_model * vec4(in_position + 1.0, 0.0, 3.0);
+#else
+  gl_Position = u_model * vec4(in_position - 0.5, 0.0, 1.0);
+#endif
 }
 
 #shader fragment
 
-precision mediump float;
+#include "common/fragment.glsl"
 in vec2 position;
-out vec4 color;
-uniform float blue;
 
 void main() {
   color = vec4(position, 1.0, 1.0);
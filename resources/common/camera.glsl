@@ -0,0 +1,5 @@
+layout(std140) uniform Camera {
+  mat4 u_view_projection;
+  mat4 u_view;
+  mat4 u_projection;
+};
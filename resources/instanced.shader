@@ -0,0 +1,29 @@
+#shader version 330 core
+#shader vertex
+#include "common/camera.glsl"
+in vec2 in_position;
+in vec2 in_uv;
+in mat4 in_instance_model;
+in vec4 in_instance_color;
+in vec4 in_instance_uv_rect;
+out vec4 instance_color;
+out vec2 uv;
+
+uniform mat4 u_model;
+
+void main() {
+  instance_color = in_instance_color;
+  uv = in_instance_uv_rect.xy + in_uv*in_instance_uv_rect.zw;
+
+  gl_Position = u_view_projection*u_model*in_instance_model*vec4(in_position, 0.0, 1.0);
+}
+
+#shader fragment
+
+#include "common/fragment.glsl"
+in vec4 instance_color;
+in vec2 uv;
+
+void main() {
+  color = instance_color*vec4(uv, blue, 1.0);
+}
@@ -0,0 +1,251 @@
+use gfx_maths::{Mat4, Vec3};
+use glow::{Context, HasContext};
+
+use crate::error::Error;
+use crate::resource::{Buffer, BufferUsage};
+
+/// Name of the uniform block the render loop writes the camera matrices
+/// to. Programs that declare it get it bound to `CAMERA_BINDING`:
+///
+/// ```glsl
+/// layout(std140) uniform Camera {
+///     mat4 u_view_projection;
+///     mat4 u_view;
+///     mat4 u_projection;
+/// };
+/// ```
+pub const CAMERA_BLOCK: &str = "Camera";
+
+/// Uniform buffer binding point of the `Camera` block.
+pub const CAMERA_BINDING: u32 = 0;
+
+/// How a `Camera` maps what it sees to the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// One world unit per window pixel at zoom 1, with the origin in the
+    /// top left corner and Y pointing down, for 2D.
+    Orthographic,
+    /// Perspective with a vertical field of view in radians, for 3D.
+    Perspective { fov_y: f32 },
+}
+
+/// The view and projection shared by every object drawn in a frame.
+///
+/// Views follow the `gfx_maths` convention: +Y is up and the camera looks
+/// along +Z.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub projection: Projection,
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    /// Magnification around the center of the view, 2 shows everything
+    /// twice as large.
+    pub zoom: f32,
+    pub near: f32,
+    pub far: f32,
+    /// Size of the window in screen coordinates, see `resize`.
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Camera {
+    /// A 2D camera covering a `width` x `height` window, the projection
+    /// objects used before cameras existed.
+    pub fn orthographic(width: f32, height: f32) -> Self {
+        Self {
+            projection: Projection::Orthographic,
+            eye: Vec3::new(0.0, 0.0, 0.0),
+            target: Vec3::new(0.0, 0.0, 1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            zoom: 1.0,
+            near: -1.0,
+            far: 1.0,
+            width,
+            height,
+        }
+    }
+
+    /// A 3D camera at the origin looking along +Z.
+    pub fn perspective(fov_y: f32, width: f32, height: f32) -> Self {
+        Self {
+            projection: Projection::Perspective { fov_y },
+            near: 0.1,
+            far: 100.0,
+            ..Self::orthographic(width, height)
+        }
+    }
+
+    /// Places the camera at `eye`, looking at `target`.
+    pub fn look_at(&mut self, eye: Vec3, target: Vec3, up: Vec3) {
+        self.eye = eye;
+        self.target = target;
+        self.up = up;
+    }
+
+    /// Follows a window resize, called by the render loop.
+    pub fn resize(&mut self, width: f32, height: f32) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// The view matrix, from world to camera coordinates.
+    pub fn view(&self) -> Mat4 {
+        let forward = (self.target - self.eye).normalized();
+        let right = self.up.cross(forward).normalized();
+        let up = forward.cross(right);
+
+        Mat4::from([
+            [right.x, up.x, forward.x, 0.0],
+            [right.y, up.y, forward.y, 0.0],
+            [right.z, up.z, forward.z, 0.0],
+            [
+                -right.dot(self.eye),
+                -up.dot(self.eye),
+                -forward.dot(self.eye),
+                1.0,
+            ],
+        ])
+    }
+
+    /// The projection matrix, from camera to clip coordinates.
+    pub fn projection(&self) -> Mat4 {
+        match self.projection {
+            Projection::Orthographic => {
+                let half_width = self.width / (2.0 * self.zoom);
+                let half_height = self.height / (2.0 * self.zoom);
+                let (center_x, center_y) = (self.width / 2.0, self.height / 2.0);
+                Mat4::orthographic_opengl(
+                    center_x - half_width,
+                    center_x + half_width,
+                    center_y + half_height,
+                    center_y - half_height,
+                    self.near,
+                    self.far,
+                )
+            }
+            Projection::Perspective { fov_y } => Mat4::perspective_opengl(
+                fov_y / self.zoom,
+                self.near,
+                self.far,
+                self.width / self.height,
+            ),
+        }
+    }
+
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection() * self.view()
+    }
+}
+
+/// The uniform buffer behind the `Camera` block, rewritten every frame by
+/// the render loop.
+#[derive(Debug)]
+pub struct CameraBuffer {
+    buffer: Buffer,
+}
+
+impl CameraBuffer {
+    /// # Safety
+    /// `gl` must be current on this thread.
+    #[track_caller]
+    pub unsafe fn new(gl: &Context) -> Result<Self, Error> {
+        let buffer = Buffer::new(gl, glow::UNIFORM_BUFFER, BufferUsage::Stream)?;
+        Ok(Self { buffer })
+    }
+
+    /// Writes the matrices of `camera` and binds the buffer to
+    /// `CAMERA_BINDING`.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn upload(&mut self, gl: &Context, camera: &Camera) {
+        let matrices = [camera.view_projection(), camera.view(), camera.projection()];
+        let data: Vec<u8> = matrices
+            .iter()
+            .flat_map(|matrix| matrix.values)
+            .flat_map(f32::to_ne_bytes)
+            .collect();
+
+        self.buffer.data(gl, &data);
+        gl.bind_buffer_base(
+            glow::UNIFORM_BUFFER,
+            CAMERA_BINDING,
+            Some(self.buffer.native()),
+        );
+    }
+
+    /// # Safety
+    /// `gl` must be current on this thread and own the buffer.
+    pub unsafe fn delete(self, gl: &Context) {
+        self.buffer.delete(gl);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gfx_maths::{Mat4, Vec3, Vec4};
+
+    use super::Camera;
+
+    /// `matrix * point` in normalized device coordinates.
+    fn project(matrix: Mat4, point: Vec3) -> Vec3 {
+        let clip = matrix * Vec4::new(point.x, point.y, point.z, 1.0);
+        Vec3::new(clip.x / clip.w, clip.y / clip.w, clip.z / clip.w)
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn maps_window_corners_to_clip_space() {
+        let camera = Camera::orthographic(800.0, 600.0);
+        let view_projection = camera.view_projection();
+
+        assert_close(
+            project(view_projection, Vec3::new(0.0, 0.0, 0.0)),
+            Vec3::new(-1.0, 1.0, 0.0),
+        );
+        assert_close(
+            project(view_projection, Vec3::new(800.0, 600.0, 0.0)),
+            Vec3::new(1.0, -1.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn zooms_around_the_center() {
+        let mut camera = Camera::orthographic(800.0, 600.0);
+        camera.zoom = 2.0;
+        let view_projection = camera.view_projection();
+
+        assert_close(
+            project(view_projection, Vec3::new(400.0, 300.0, 0.0)),
+            Vec3::new(0.0, 0.0, 0.0),
+        );
+        assert_close(
+            project(view_projection, Vec3::new(200.0, 150.0, 0.0)),
+            Vec3::new(-1.0, 1.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn looks_from_the_eye_to_the_target() {
+        let mut camera = Camera::perspective(1.0, 800.0, 600.0);
+        camera.look_at(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(1.0, 2.0, 13.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let view = camera.view();
+
+        assert_close(project(view, camera.eye), Vec3::new(0.0, 0.0, 0.0));
+        assert_close(project(view, camera.target), Vec3::new(0.0, 0.0, 10.0));
+        let center = project(camera.view_projection(), camera.target);
+        assert!(
+            center.x.abs() < 1e-4 && center.y.abs() < 1e-4,
+            "{:?}",
+            center
+        );
+    }
+}
@@ -0,0 +1,140 @@
+#[cfg(feature = "validate")]
+use gl_object::shaders::diagnostic::Severity;
+#[cfg(feature = "translate")]
+use gl_object::shaders::translate::translate_file;
+#[cfg(feature = "validate")]
+use gl_object::shaders::validate::validate;
+
+/// Runs the subcommand named by the first argument and returns the exit
+/// code, or `None` when the demo window should open instead.
+pub fn run(args: &[String]) -> Option<i32> {
+    match args.first().map(String::as_str) {
+        Some("check") => Some(check(&args[1..])),
+        Some("translate") => Some(translate(&args[1..])),
+        _ => None,
+    }
+}
+
+/// `gl_object_bin check <files...>`: validates `.shader` files without a GL
+/// context and fails when any of them has errors.
+#[cfg(feature = "validate")]
+fn check(files: &[String]) -> i32 {
+    if files.is_empty() {
+        eprintln!("usage: gl_object_bin check <files...>");
+        return 2;
+    }
+
+    let mut failed = 0;
+    for file in files {
+        match validate(file) {
+            Ok(diagnostics) => {
+                for diagnostic in diagnostics.iter() {
+                    println!("{}", diagnostic);
+                }
+                if diagnostics
+                    .iter()
+                    .any(|diagnostic| diagnostic.severity == Severity::Error)
+                {
+                    failed += 1;
+                }
+            }
+            Err(error) => {
+                println!("{}", error);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        eprintln!("{} of {} files failed", failed, files.len());
+        1
+    } else {
+        0
+    }
+}
+
+#[cfg(not(feature = "validate"))]
+fn check(_files: &[String]) -> i32 {
+    eprintln!("gl_object_bin was built without the `validate` feature");
+    2
+}
+
+/// `gl_object_bin translate <files...>`: writes SPIR-V and WGSL for every
+/// stage next to each `.shader` file.
+#[cfg(feature = "translate")]
+fn translate(files: &[String]) -> i32 {
+    if files.is_empty() {
+        eprintln!("usage: gl_object_bin translate <files...>");
+        return 2;
+    }
+
+    let mut failed = 0;
+    for file in files {
+        match translate_file(file) {
+            Ok(written) => {
+                for path in written {
+                    println!("wrote {}", path.display());
+                }
+            }
+            Err(error) => {
+                println!("{}", error);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        eprintln!("{} of {} files failed", failed, files.len());
+        1
+    } else {
+        0
+    }
+}
+
+#[cfg(not(feature = "translate"))]
+fn translate(_files: &[String]) -> i32 {
+    eprintln!("gl_object_bin was built without the `translate` feature");
+    2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn opens_the_window_without_a_subcommand() {
+        assert_eq!(run(&args(&[])), None);
+        assert_eq!(run(&args(&["--help"])), None);
+    }
+
+    #[cfg(feature = "validate")]
+    #[test]
+    fn checks_shaders_with_exit_codes() {
+        let broken = std::env::temp_dir().join(format!(
+            "gl_object_cli_broken_{}.shader",
+            std::process::id()
+        ));
+        std::fs::write(
+            &broken,
+            "#shader version 330 core\n#shader vertex\nvoid main() {\n  gl_Position = missing;\n}\n",
+        )
+        .unwrap();
+        let broken = broken.to_str().unwrap();
+
+        assert_eq!(run(&args(&["check"])), Some(2));
+        assert_eq!(
+            run(&args(&["check", "resources/instanced.shader"])),
+            Some(0)
+        );
+        assert_eq!(run(&args(&["check", broken])), Some(1));
+        assert_eq!(
+            run(&args(&["check", "resources/instanced.shader", broken])),
+            Some(1)
+        );
+        assert_eq!(run(&args(&["check", "resources/missing.shader"])), Some(1));
+    }
+}
@@ -0,0 +1,62 @@
+use std::fmt;
+
+use crate::program::uniform::UniformError;
+use crate::scene::SceneError;
+use crate::shaders::ShaderError;
+
+/// Everything that can go wrong in this crate, for callers that want one
+/// error type.
+#[derive(Debug)]
+pub enum Error {
+    /// The window system or the GL context could not be set up.
+    Display(String),
+    /// `render` was called before `create_display` succeeded.
+    NoDisplay,
+    /// The driver could not create a GL object.
+    Gl(String),
+    Shader(ShaderError),
+    Uniform(UniformError),
+    Scene(SceneError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Display(message) => write!(f, "cannot create display: {}", message),
+            Error::NoDisplay => write!(f, "no display, create_display was not called"),
+            Error::Gl(message) => write!(f, "cannot create GL object: {}", message),
+            Error::Shader(error) => error.fmt(f),
+            Error::Uniform(error) => error.fmt(f),
+            Error::Scene(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Shader(error) => Some(error),
+            Error::Uniform(error) => Some(error),
+            Error::Scene(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ShaderError> for Error {
+    fn from(error: ShaderError) -> Self {
+        Error::Shader(error)
+    }
+}
+
+impl From<UniformError> for Error {
+    fn from(error: UniformError) -> Self {
+        Error::Uniform(error)
+    }
+}
+
+impl From<SceneError> for Error {
+    fn from(error: SceneError) -> Self {
+        Error::Scene(error)
+    }
+}
@@ -1,6 +1,8 @@
 use imgui_opengl_renderer::Renderer;
 
+use crate::error::Error;
 use crate::object::OpenGLObjectTrait;
+use crate::program::cache::ProgramCache;
 
 pub struct ImguiCtx {
     imgui_ctx: imgui::Context,
@@ -31,7 +33,7 @@ impl ImguiCtx {
 }
 
 impl OpenGLObjectTrait for ImguiCtx {
-    fn attach(&mut self, _gl: &glow::Context) {
+    fn attach(&mut self, _gl: &glow::Context, _programs: &mut ProgramCache) -> Result<(), Error> {
         let io = self.imgui_ctx.io_mut();
         let (win_w, win_h) = (800, 600);
         let (draw_w, draw_h) = (1600, 1200);
@@ -41,6 +43,7 @@ impl OpenGLObjectTrait for ImguiCtx {
             (draw_w as f32) / (win_w as f32),
             (draw_h as f32) / (win_h as f32),
         ];
+        Ok(())
     }
 
     fn render(&mut self, _gl: &glow::Context) {
@@ -50,12 +53,10 @@ impl OpenGLObjectTrait for ImguiCtx {
         self.renderer.render(ui);
     }
 
-    fn detach(&mut self, _gl: &glow::Context) {
+    fn detach(&mut self, _gl: &glow::Context, _programs: &mut ProgramCache) {
         drop(&self.imgui_ctx);
     }
 
-    fn move_model(&mut self, _movement_x: f32, _movement_y: f32, _movement_z: f32) {}
-
     fn window_resize(&mut self, draw_size: [f32; 2], size: [f32; 2]) {
         let io = self.imgui_ctx.io_mut();
         let (win_w, win_h) = (size[0], size[1]);
@@ -67,6 +68,4 @@ impl OpenGLObjectTrait for ImguiCtx {
             (draw_h as f32) / (win_h as f32),
         ];
     }
-
-    fn set_model(&mut self, _movement_x: f32, _movement_y: f32, _movement_z: f32) {}
 }
@@ -1,32 +1,100 @@
+use crate::primitives::instanced::{Instance, InstancedRectangle};
 use crate::primitives::rectangle::Rectangle;
+use crate::scene::Scene;
+use crate::transform::Transform;
 use crate::window::Window;
+use gfx_maths::Vec3;
 use window::WindowTrait;
 
+// `#[derive(Vertex)]` names paths through `::gl_object`, also inside this crate
+extern crate self as gl_object;
+
+pub mod camera;
+pub mod error;
 pub mod imgui_ctx;
 pub mod object;
 pub mod primitives;
+pub mod program;
+pub mod resource;
+pub mod scene;
 pub mod shaders;
-mod window;
+pub mod transform;
+pub mod vertex;
+pub mod window;
+
+pub use error::Error;
 
-pub fn glfw_example() {
+pub fn glfw_example() -> Result<(), Error> {
     let mut handle =
         Window::<glfw::Glfw, glfw::Window>::new(800, 600, format!("GLFW {}", "Window".to_string()));
 
-    handle.create_display();
+    handle.create_display()?;
     let rectangle = &mut Rectangle::new(200, 200, "resources/shader_with_matrix.shader");
 
     handle.render(&mut vec![rectangle])
 }
 
+/// A bar growing every frame, reached through the scene by its type.
+pub fn scene_example() -> Result<(), Error> {
+    let mut handle = Window::<glfw::Glfw, glfw::Window>::new(800, 600, "GLFW Scene".to_string());
+
+    handle.create_display()?;
+    let mut scene = Scene::new();
+    let bar = scene.add_object(
+        None,
+        Rectangle::new(0, 20, "resources/shader_with_matrix.shader"),
+    )?;
+    if let Some(transform) = scene.transform_mut(bar) {
+        transform.translate(Vec3::new(100.0, 290.0, 0.0));
+    }
+
+    handle.render_scene_with(&mut scene, |scene| {
+        if let Some(bar) = scene.get_mut_as::<Rectangle>(bar) {
+            bar.width = (bar.width + 1) % 600;
+        }
+    })
+}
+
+/// The instanced rendering example of the README: 10 000 sprites in one
+/// draw call, one of them turning and the last ones fading out.
+pub fn instanced_example() -> Result<(), Error> {
+    let mut handle =
+        Window::<glfw::Glfw, glfw::Window>::new(800, 600, "GLFW Instanced".to_string());
+
+    handle.create_display()?;
+    let mut sprites = InstancedRectangle::new(16, 16, "resources/instanced.shader");
+    for i in 0..10_000 {
+        let position = Vec3::new((i % 100) as f32 * 20.0, (i / 100) as f32 * 20.0, 0.0);
+        let transform = Transform::from_translation(position).with_pivot(Vec3::new(8.0, 8.0, 0.0));
+        sprites.push(Instance::new(transform).with_color([1.0, 0.5, 0.0, 1.0]));
+    }
+    let mut scene = Scene::new();
+    let node = scene.add_object(None, sprites)?;
+
+    handle.render_scene_with(&mut scene, |scene| {
+        let sprites = match scene.get_mut_as::<InstancedRectangle>(node) {
+            Some(sprites) => sprites,
+            None => return,
+        };
+        if let Some(sprite) = sprites.instances.get_mut(42) {
+            sprite.transform.rotate_z(0.1);
+        }
+        if let Some(sprite) = sprites.instances.last_mut() {
+            sprite.color[3] -= 0.05;
+        }
+        sprites.instances.retain(|instance| instance.color[3] > 0.0);
+    })
+}
+
 #[cfg(feature = "sdl2")]
-pub fn sdl2_example() {
+pub fn sdl2_example() -> Result<(), Error> {
     let mut handle = Window::<sdl2::Sdl, sdl2::video::Window>::new(
         800,
         600,
         format!("SDL {}", "Window".to_string()),
     );
 
-    handle.create_display();
+    handle.create_display()?;
     let rectangle = &mut Rectangle::new(200, 200, "resources/shader_with_matrix.shader");
 
     handle.render(&mut vec![rectangle])
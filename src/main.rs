@@ -1,15 +1,18 @@
-use crate::primitives::rectangle::Rectangle;
-use crate::primitives::triangle;
-use crate::window::Window;
-use crate::window::WindowTrait;
+use gl_object::primitives::instanced::{Instance, InstancedRectangle};
+use gl_object::primitives::rectangle::Rectangle;
+use gl_object::primitives::triangle;
+use gl_object::window::Window;
+use gl_object::window::WindowTrait;
+use gl_object::{imgui_ctx, object, shaders, transform};
 
-mod imgui_ctx;
-mod object;
-mod primitives;
-pub mod shaders;
-mod window;
+mod cli;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
+
     println!("Hello, world!");
 
     let width = 800_u32;
@@ -35,12 +38,34 @@ fn main() {
 
     let triangle2 = &mut triangle::Triangle::new(
         [0.5f32, 1.0f32, 0.0f32, 0.0f32, 1.0f32, 0.0f32],
-        "resources/shader_with_uniform.shader",
-    );
+        "resources/base_shader.shader",
+    )
+    .with_defines(&[("SHIFTED", "")]);
+
+    let grid = &mut InstancedRectangle::new(16, 16, "resources/instanced.shader");
+    for row in 0..10 {
+        for column in 0..10 {
+            let translation =
+                gfx_maths::Vec3::new(400.0 + column as f32 * 20.0, 50.0 + row as f32 * 20.0, 0.0);
+            grid.push(
+                Instance::new(transform::Transform::from_translation(translation)).with_color([
+                    column as f32 / 9.0,
+                    row as f32 / 9.0,
+                    1.0,
+                    1.0,
+                ]),
+            );
+        }
+    }
     objects.push(rectangle1);
     objects.push(triangle2);
+    objects.push(grid);
 
-    handle.create_display();
+    if let Err(error) = handle.create_display() {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+    handle.shader_watcher = Some(shaders::watcher::ShaderWatcher::new());
 
     let mut imgui_ctx = None;
     if !sdl {
@@ -49,9 +74,12 @@ fn main() {
         }));
     }
 
-    if imgui_ctx.is_some() {
-        objects.push(imgui_ctx.as_mut().unwrap());
+    if let Some(ctx) = imgui_ctx.as_mut() {
+        objects.push(ctx);
     }
 
-    handle.render(objects)
+    if let Err(error) = handle.render(objects) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
 }
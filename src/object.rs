@@ -1,12 +1,159 @@
-use crate::shaders::ShaderData;
+use crate::error::Error;
+use crate::program;
+use crate::program::cache::{ProgramCache, ProgramHandle};
+use crate::program::Program;
+use crate::resource::{Buffer, BufferUsage, VertexArray};
+use crate::shaders::{ShaderError, ShaderSource};
+use crate::transform::Transform;
+use crate::vertex::{index_bytes, vertex_bytes, Vertex, VertexLayout};
 use gfx_maths::{Mat4, Vec3};
-use glow::{Context, HasContext, NativeProgram};
+use glow::{Context, NativeProgram};
+use std::any::Any;
+use std::ops::Range;
 
 #[derive(Debug)]
 pub struct BufferData {
-    pub vbo: glow::NativeBuffer,
-    pub vao: glow::NativeVertexArray,
-    pub ibo: glow::NativeBuffer,
+    /// One vertex buffer per buffer of the `VertexLayout`.
+    pub vbos: Vec<Buffer>,
+    pub vao: VertexArray,
+    pub ibo: Buffer,
+}
+
+impl BufferData {
+    /// # Safety
+    /// `gl` must be current on this thread and own the buffers.
+    pub unsafe fn delete(self, gl: &Context) {
+        Self::delete_parts(gl, self.vao, self.vbos, Some(self.ibo));
+    }
+
+    /// Deletes what was created of a `BufferData` that failed to build.
+    unsafe fn delete_parts(gl: &Context, vao: VertexArray, vbos: Vec<Buffer>, ibo: Option<Buffer>) {
+        vao.delete(gl);
+        for vbo in vbos {
+            vbo.delete(gl);
+        }
+        if let Some(ibo) = ibo {
+            ibo.delete(gl);
+        }
+    }
+}
+
+/// The shader a primitive draws with and the program it got for it, shared
+/// by `Rectangle`, `Triangle` and `InstancedRectangle`.
+///
+/// Changes take effect on the next `acquire`, which also tells when vertex
+/// arrays must be rebuilt because attribute locations may have moved.
+#[derive(Debug)]
+pub struct ShaderSelection {
+    source: ShaderSource,
+    defines: Vec<(String, String)>,
+    /// Set when the shader changed after `program` was fetched.
+    changed: bool,
+    program: Option<ProgramHandle>,
+    /// Generation of `program` as of the last `acquire`.
+    generation: u64,
+}
+
+impl ShaderSelection {
+    pub fn new(source: impl Into<ShaderSource>) -> Self {
+        Self {
+            source: source.into(),
+            defines: vec![],
+            changed: false,
+            program: None,
+            generation: 0,
+        }
+    }
+
+    pub fn source(&self) -> &ShaderSource {
+        &self.source
+    }
+
+    pub fn defines(&self) -> &[(String, String)] {
+        &self.defines
+    }
+
+    /// The program of the last `acquire`, until `release`.
+    pub fn program(&self) -> Option<&ProgramHandle> {
+        self.program.as_ref()
+    }
+
+    /// Switches to another shader from the next update.
+    pub fn set_shader(&mut self, source: impl Into<ShaderSource>) {
+        self.source = source.into();
+        self.changed = true;
+    }
+
+    /// Switches to another permutation of the shader from the next update,
+    /// with `#define name value` lines injected, see
+    /// `ShaderDataBuilder::define`.
+    pub fn set_defines(&mut self, defines: &[(&str, &str)]) {
+        self.defines = defines
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        self.changed = true;
+    }
+
+    /// Returns the program for the current shader, fetching it on first use
+    /// and after the shader changed. `uploaded` is cleared when it is not
+    /// the program, or the generation of it, the last call returned: a new
+    /// program, or a hot reload, may have moved the attribute locations, so
+    /// vertex arrays built since must be rebuilt.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn acquire<T>(
+        &mut self,
+        gl: &Context,
+        programs: &mut ProgramCache,
+        uploaded: &mut Option<T>,
+    ) -> Result<&ProgramHandle, ShaderError> {
+        if self.changed {
+            self.release(gl, programs);
+            self.changed = false;
+        }
+        let fetched = self.program.is_none();
+        let handle = match self.program {
+            Some(ref handle) => handle,
+            None => {
+                self.program
+                    .insert(programs.get_permutation(gl, &self.source, &self.defines)?)
+            }
+        };
+        if fetched || handle.generation() != self.generation {
+            *uploaded = None;
+        }
+        self.generation = handle.generation();
+        Ok(handle)
+    }
+
+    /// Binds the program of the last `acquire` and sets its `u_model`
+    /// uniform to `model`. Returns `false` when there is no program yet.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn bind(&self, gl: &Context, model: Mat4) -> bool {
+        let program = match &self.program {
+            Some(handle) => handle.program(),
+            None => return false,
+        };
+        program.bind(gl);
+        // Unknown names and type mismatches are recorded on the program and
+        // logged once by `ProgramCache::report_uniforms`
+        let _ = program.set_uniform(gl, "u_model", model);
+        true
+    }
+
+    /// Gives the program back to `programs`.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn release(&mut self, gl: &Context, programs: &mut ProgramCache) {
+        if let Some(handle) = self.program.take() {
+            programs.release(gl, handle);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -30,34 +177,48 @@ impl TestingEvent {
     }
 }
 
-#[derive(Debug)]
-pub struct MVP {
-    pub model: Vec3,
-    pub view: Mat4,
-    pub projection: Mat4,
-}
+/// The range of `new` to write over `old` so a buffer holding `old` ends up
+/// holding `new`: from the first to the last element that differs, or to
+/// the end when `new` is longer. `None` when nothing needs writing; a
+/// shorter `new` leaves the elements past its end in place.
+pub(crate) fn changed_range<V: PartialEq>(old: &[V], new: &[V]) -> Option<Range<usize>> {
+    let shared = old.len().min(new.len());
+    let differs = |index: &usize| old[*index] != new[*index];
 
-impl MVP {
-    pub fn new(screen_width: u32, screen_height: u32) -> Self {
-        Self {
-            projection: Mat4::orthographic_opengl(
-                0.0,
-                screen_width as f32,
-                screen_height as f32,
-                0.0,
-                -1.0,
-                1.0,
-            ),
-            view: Mat4::identity(),
-            model: Vec3::new(0.0, 0.0, 0.0),
-        }
-    }
+    let first = match (0..shared).find(differs) {
+        Some(first) => first,
+        None if new.len() > shared => shared,
+        None => return None,
+    };
+    let end = if new.len() > shared {
+        new.len()
+    } else {
+        (first..shared)
+            .rfind(differs)
+            .map_or(first, |last| last + 1)
+    };
+    Some(first..end)
 }
 
+/// An object drawn by `WindowTrait::render`.
+///
+/// The render loop calls `attach` once before the first frame, then
+/// `update` and `render` every frame, and `detach` once when it stops.
 pub trait OpenGLObjectTrait {
-    fn attach(&mut self, gl: &Context);
+    /// Creates the GPU resources of the object.
+    fn attach(&mut self, gl: &Context, programs: &mut ProgramCache) -> Result<(), Error>;
+
+    /// Called every frame before any object renders. Rebuilds the GPU
+    /// resources whose geometry or shader changed since the last frame.
+    fn update(&mut self, _gl: &Context, _programs: &mut ProgramCache) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Draws the object, binding everything it needs.
     fn render(&mut self, gl: &Context);
-    fn detach(&mut self, gl: &Context);
+
+    /// Releases what `attach` and `update` created.
+    fn detach(&mut self, gl: &Context, programs: &mut ProgramCache);
 
     fn get_movement_model(movement_x: f32, movement_y: f32, movement_z: f32) -> Mat4
     where
@@ -66,95 +227,273 @@ pub trait OpenGLObjectTrait {
         Mat4::translate(Vec3::new(movement_x, movement_y, movement_z))
     }
 
-    fn move_model(&mut self, movement_x: f32, movement_y: f32, movement_z: f32);
-    fn window_resize(&mut self, draw_size: [f32; 2], size: [f32; 2]);
-
-    unsafe fn setup_shaders(gl: &Context, program: &NativeProgram, source: String)
-    where
-        Self: Sized,
-    {
-        let shaders = ShaderData::new(source);
+    /// Where the object is placed, for objects that can be moved.
+    fn transform(&self) -> Option<&Transform> {
+        None
+    }
 
-        let shader_sources = [
-            (glow::VERTEX_SHADER, shaders.vertex_shader.source),
-            (glow::FRAGMENT_SHADER, shaders.fragment_shader.source),
-        ];
+    fn transform_mut(&mut self) -> Option<&mut Transform> {
+        None
+    }
 
-        let mut shaders = Vec::with_capacity(shader_sources.len());
+    /// Called by a `Scene` with the world matrix of the node holding the
+    /// object whenever it changes, to be applied on top of `transform`.
+    fn set_parent_matrix(&mut self, _matrix: Mat4) {}
 
-        for (shader_type, shader_source) in shader_sources.iter() {
-            let shader = gl.create_shader(*shader_type).unwrap();
+    /// The shader the object draws with. A `Scene` watches it when the
+    /// object fails, and attaches the object again once the file changes.
+    fn shader_source(&self) -> Option<&ShaderSource> {
+        None
+    }
 
-            gl.shader_source(shader, shader_source);
+    /// The `#define`s `shader_source` is compiled with.
+    fn shader_defines(&self) -> &[(String, String)] {
+        &[]
+    }
 
-            gl.compile_shader(shader);
+    /// The object as `Any`, so `Scene::get_as` can hand out its concrete
+    /// type. Objects that borrow data cannot be `Any` and keep the default.
+    fn as_any(&self) -> Option<&dyn Any> {
+        None
+    }
 
-            if !gl.get_shader_compile_status(shader) {
-                panic!(
-                    "Shader compilation failed: {}",
-                    gl.get_shader_info_log(shader)
-                );
-            }
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        None
+    }
 
-            gl.attach_shader(*program, shader);
+    /// Moves the object by the given offset. Does nothing for objects
+    /// without a `transform`.
+    fn move_model(&mut self, movement_x: f32, movement_y: f32, movement_z: f32) {
+        if let Some(transform) = self.transform_mut() {
+            transform.translate(Vec3::new(movement_x, movement_y, movement_z));
+        }
+    }
 
-            shaders.push(shader);
+    /// Moves the object to the given position, keeping its rotation and
+    /// scale.
+    fn set_model(&mut self, movement_x: f32, movement_y: f32, movement_z: f32) {
+        if let Some(transform) = self.transform_mut() {
+            transform.translation = Vec3::new(movement_x, movement_y, movement_z);
         }
+    }
+
+    fn window_resize(&mut self, draw_size: [f32; 2], size: [f32; 2]);
 
-        gl.link_program(*program);
+    /// Compiles the stages of `source` and links them into `program`.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread and `program` must belong to it.
+    unsafe fn setup_shaders(
+        gl: &Context,
+        program: &NativeProgram,
+        source: impl Into<ShaderSource>,
+    ) -> Result<(), ShaderError>
+    where
+        Self: Sized,
+    {
+        program::setup_shaders(gl, program, source)
+    }
 
-        if !gl.get_program_link_status(*program) {
-            panic!("{}", gl.get_program_info_log(*program));
+    /// Uploads one slice of `vertices` per buffer of `layout`, plus the
+    /// indices, and records them in a vertex array. Attributes are bound to
+    /// the locations `program` reports for their names. `usage` applies to
+    /// the vertex buffers; indices are static.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread and `program` must belong to it.
+    #[track_caller]
+    unsafe fn setup_buffers(
+        gl: &Context,
+        program: &Program,
+        usage: BufferUsage,
+        layout: &VertexLayout,
+        vertices: &[&[u8]],
+        indices: &[u32],
+    ) -> Result<BufferData, Error>
+    where
+        Self: Sized,
+    {
+        for mismatch in layout.check(program.reflection()) {
+            eprintln!("{}", mismatch);
         }
 
-        for shader in shaders {
-            gl.detach_shader(*program, shader);
-            gl.delete_shader(shader);
+        // We construct the vertex array first so it records the buffers
+        let vao = VertexArray::new(gl)?;
+        vao.bind(gl);
+
+        let mut vbos = Vec::with_capacity(vertices.len());
+        for vertices in vertices {
+            match Buffer::new(gl, glow::ARRAY_BUFFER, usage) {
+                Ok(mut vbo) => {
+                    vbo.data(gl, vertices);
+                    vbos.push(vbo);
+                }
+                Err(error) => {
+                    BufferData::delete_parts(gl, vao, vbos, None);
+                    return Err(error);
+                }
+            }
         }
+        layout.bind(gl, &vbos, program.reflection());
+
+        let mut ibo = match Buffer::new(gl, glow::ELEMENT_ARRAY_BUFFER, BufferUsage::Static) {
+            Ok(ibo) => ibo,
+            Err(error) => {
+                BufferData::delete_parts(gl, vao, vbos, None);
+                return Err(error);
+            }
+        };
+        ibo.data(gl, index_bytes(indices));
+
+        Ok(BufferData { vbos, vao, ibo })
     }
 
-    unsafe fn setup_buffers(
+    /// Like `setup_buffers` for a single buffer of `Vertex` structs, which
+    /// describe their own layout.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread and `program` must belong to it.
+    #[track_caller]
+    unsafe fn setup_vertex_buffers<V: Vertex>(
         gl: &Context,
-        vertices: &[f32],
+        program: &Program,
+        usage: BufferUsage,
+        vertices: &[V],
         indices: &[u32],
-        vao_size: i32,
-        vao_stride: i32,
-    ) -> BufferData
+    ) -> Result<BufferData, Error>
     where
         Self: Sized,
     {
-        let (vbo, vao, ibo) = {
-            let triangle_vertices_u8: &[u8] = core::slice::from_raw_parts(
-                vertices.as_ptr() as *const u8,
-                vertices.len() * core::mem::size_of::<f32>(),
-            );
-
-            let indices = indices;
-
-            let indices_u8 = core::slice::from_raw_parts(
-                indices.as_ptr() as *const u8,
-                indices.len() * core::mem::size_of::<u32>(),
-            );
-
-            // We construct a buffer and upload the data
-            let vbo = gl.create_buffer().unwrap();
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
-            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, triangle_vertices_u8, glow::STATIC_DRAW);
-
-            // We now construct a vertex array to describe the format of the input buffer
-            let vao = gl.create_vertex_array().unwrap();
-            gl.bind_vertex_array(Some(vao));
-            gl.enable_vertex_attrib_array(0);
-            gl.vertex_attrib_pointer_f32(0, vao_size, glow::FLOAT, false, vao_stride, 0);
-
-            let ibo = gl.create_buffer().unwrap();
-            gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ibo));
-            gl.buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, indices_u8, glow::STATIC_DRAW);
-
-            (vbo, vao, ibo)
-        };
-        BufferData { vbo, vao, ibo }
+        Self::setup_buffers(
+            gl,
+            program,
+            usage,
+            &VertexLayout::new(vec![V::layout()]),
+            &[vertex_bytes(vertices)],
+            indices,
+        )
+    }
+
+    /// Writes the vertices of `new` that differ from `old`, the contents of
+    /// `buffer`, as one range. The buffer grows when `new` is longer.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread and own `buffer`.
+    unsafe fn update_vertex_buffer<V: Vertex + PartialEq>(
+        gl: &Context,
+        buffer: &mut Buffer,
+        old: &[V],
+        new: &[V],
+    ) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        match changed_range(old, new) {
+            Some(range) => buffer.sub_data(
+                gl,
+                range.start * std::mem::size_of::<V>(),
+                vertex_bytes(&new[range]),
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Lets scenes hold borrowed objects, see `Scene::add_object`.
+impl<T: OpenGLObjectTrait + ?Sized> OpenGLObjectTrait for &mut T {
+    fn attach(&mut self, gl: &Context, programs: &mut ProgramCache) -> Result<(), Error> {
+        (**self).attach(gl, programs)
+    }
+
+    fn update(&mut self, gl: &Context, programs: &mut ProgramCache) -> Result<(), Error> {
+        (**self).update(gl, programs)
+    }
+
+    fn render(&mut self, gl: &Context) {
+        (**self).render(gl)
     }
 
-    fn set_model(&mut self, movement_x: f32, movement_y: f32, movement_z: f32);
+    fn detach(&mut self, gl: &Context, programs: &mut ProgramCache) {
+        (**self).detach(gl, programs)
+    }
+
+    fn transform(&self) -> Option<&Transform> {
+        (**self).transform()
+    }
+
+    fn transform_mut(&mut self) -> Option<&mut Transform> {
+        (**self).transform_mut()
+    }
+
+    fn set_parent_matrix(&mut self, matrix: Mat4) {
+        (**self).set_parent_matrix(matrix)
+    }
+
+    fn shader_source(&self) -> Option<&ShaderSource> {
+        (**self).shader_source()
+    }
+
+    fn shader_defines(&self) -> &[(String, String)] {
+        (**self).shader_defines()
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        (**self).as_any()
+    }
+
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        (**self).as_any_mut()
+    }
+
+    fn move_model(&mut self, movement_x: f32, movement_y: f32, movement_z: f32) {
+        (**self).move_model(movement_x, movement_y, movement_z)
+    }
+
+    fn window_resize(&mut self, draw_size: [f32; 2], size: [f32; 2]) {
+        (**self).window_resize(draw_size, size)
+    }
+
+    fn set_model(&mut self, movement_x: f32, movement_y: f32, movement_z: f32) {
+        (**self).set_model(movement_x, movement_y, movement_z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::changed_range;
+
+    #[test]
+    fn writes_nothing_when_unchanged() {
+        assert_eq!(changed_range(&[1, 2, 3], &[1, 2, 3]), None);
+        assert_eq!(changed_range::<i32>(&[], &[]), None);
+    }
+
+    #[test]
+    fn writes_a_single_changed_element() {
+        assert_eq!(changed_range(&[1, 2, 3], &[1, 5, 3]), Some(1..2));
+        assert_eq!(changed_range(&[1, 2, 3], &[0, 2, 3]), Some(0..1));
+        assert_eq!(changed_range(&[1, 2, 3], &[1, 2, 0]), Some(2..3));
+    }
+
+    #[test]
+    fn spans_first_to_last_change() {
+        assert_eq!(
+            changed_range(&[1, 2, 3, 4, 5], &[1, 0, 3, 0, 5]),
+            Some(1..4)
+        );
+    }
+
+    #[test]
+    fn appends_past_the_end() {
+        assert_eq!(changed_range(&[1, 2], &[1, 2, 3, 4]), Some(2..4));
+        assert_eq!(changed_range(&[], &[1, 2]), Some(0..2));
+        assert_eq!(changed_range(&[1, 2], &[0, 2, 3]), Some(0..3));
+    }
+
+    #[test]
+    fn shrinks_without_rewriting_the_rest() {
+        assert_eq!(changed_range(&[1, 2, 3], &[1, 2]), None);
+        assert_eq!(changed_range(&[1, 2, 3], &[1, 0]), Some(1..2));
+        assert_eq!(changed_range(&[1, 2, 3], &[]), None);
+    }
 }
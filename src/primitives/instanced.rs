@@ -0,0 +1,328 @@
+use crate::error::Error;
+use crate::object::BufferData;
+use crate::object::{OpenGLObjectTrait, ShaderSelection};
+use crate::program::cache::ProgramCache;
+use crate::resource::BufferUsage;
+use crate::shaders::ShaderSource;
+use crate::transform::Transform;
+use crate::vertex::{vertex_bytes, Vertex, VertexLayout};
+use gfx_maths::Mat4;
+use glow::HasContext;
+use std::any::Any;
+
+/// A corner of the rectangle every instance draws.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Vertex)]
+struct QuadVertex {
+    in_position: [f32; 2],
+    /// `[0, 1]` across the rectangle, mapped into the UV rect of the
+    /// instance.
+    in_uv: [f32; 2],
+}
+
+/// What the instance buffer holds for one `Instance`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Vertex)]
+struct InstanceData {
+    #[vertex(name = "in_instance_model")]
+    model: Mat4,
+    #[vertex(name = "in_instance_color")]
+    color: [f32; 4],
+    #[vertex(name = "in_instance_uv_rect")]
+    uv_rect: [f32; 4],
+}
+
+/// One copy of the rectangle of an `InstancedRectangle`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Instance {
+    pub transform: Transform,
+    /// RGBA, read as `in_instance_color`.
+    pub color: [f32; 4],
+    /// Part of a texture covered by the rectangle, as `[u, v, width,
+    /// height]`, read as `in_instance_uv_rect`.
+    pub uv_rect: [f32; 4],
+}
+
+impl Default for Instance {
+    fn default() -> Self {
+        Self::new(Transform::new())
+    }
+}
+
+impl Instance {
+    /// A white instance covering the whole texture.
+    pub fn new(transform: Transform) -> Self {
+        Self {
+            transform,
+            color: [1.0, 1.0, 1.0, 1.0],
+            uv_rect: [0.0, 0.0, 1.0, 1.0],
+        }
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_uv_rect(mut self, uv_rect: [f32; 4]) -> Self {
+        self.uv_rect = uv_rect;
+        self
+    }
+
+    fn data(&self) -> InstanceData {
+        InstanceData {
+            model: self.transform.matrix(),
+            color: self.color,
+            uv_rect: self.uv_rect,
+        }
+    }
+}
+
+/// Many copies of one `width` x `height` rectangle, drawn with a single
+/// instanced draw call.
+///
+/// `instances` can be pushed, changed or removed every frame; `update`
+/// rewrites only the range of the instance buffer that changed. The
+/// shader reads the corners as `in_position` and `in_uv`, and every
+/// instance as `in_instance_model`, `in_instance_color` and
+/// `in_instance_uv_rect`, see `resources/instanced.shader`.
+#[derive(Debug)]
+pub struct InstancedRectangle {
+    pub width: u32,
+    pub height: u32,
+    pub instances: Vec<Instance>,
+    buffers: Option<BufferData>,
+    /// Corners last written to the first buffer.
+    uploaded_quad: Option<[QuadVertex; 4]>,
+    /// Instances last written to the second buffer.
+    uploaded: Vec<InstanceData>,
+    /// The shader and the program drawn with it.
+    pub shader: ShaderSelection,
+    /// Applied to every instance through the `u_model` uniform.
+    pub transform: Transform,
+    /// World matrix of the scene node holding the rectangles.
+    parent_matrix: Mat4,
+}
+
+impl InstancedRectangle {
+    pub fn new(width: u32, height: u32, source: impl Into<ShaderSource>) -> Self {
+        Self {
+            width,
+            height,
+            instances: vec![],
+            buffers: None,
+            uploaded_quad: None,
+            uploaded: vec![],
+            shader: ShaderSelection::new(source),
+            transform: Transform::new(),
+            parent_matrix: Mat4::identity(),
+        }
+    }
+
+    /// Compiles the shader with `#define name value` lines injected, see
+    /// `ShaderDataBuilder::define`.
+    pub fn with_defines(mut self, defines: &[(&str, &str)]) -> Self {
+        self.shader.set_defines(defines);
+        self
+    }
+
+    /// The corners in the first buffer, read per vertex, and the instances
+    /// in the second, read per instance.
+    fn layout() -> VertexLayout {
+        VertexLayout::new(vec![
+            QuadVertex::layout(),
+            InstanceData::layout().instanced(),
+        ])
+    }
+
+    /// Adds an instance, drawn from the next update, returning its index.
+    pub fn push(&mut self, instance: Instance) -> usize {
+        self.instances.push(instance);
+        self.instances.len() - 1
+    }
+}
+
+impl OpenGLObjectTrait for InstancedRectangle {
+    fn attach(&mut self, gl: &glow::Context, programs: &mut ProgramCache) -> Result<(), Error> {
+        self.update(gl, programs)
+    }
+
+    /// Rewrites the corners when the size changed and the instances that
+    /// changed since the last update.
+    fn update(&mut self, gl: &glow::Context, programs: &mut ProgramCache) -> Result<(), Error> {
+        unsafe {
+            let handle = self.shader.acquire(gl, programs, &mut self.uploaded_quad)?;
+
+            let (width, height) = (self.width as f32, self.height as f32);
+            let corner = |x: f32, y: f32| QuadVertex {
+                in_position: [x * width, y * height],
+                in_uv: [x, y],
+            };
+            let quad = [
+                corner(0.0, 0.0),
+                corner(0.0, 1.0),
+                corner(1.0, 1.0),
+                corner(1.0, 0.0),
+            ];
+            let instances: Vec<InstanceData> = self.instances.iter().map(Instance::data).collect();
+
+            match (self.buffers.as_mut(), self.uploaded_quad.as_ref()) {
+                (Some(buffers), Some(uploaded_quad)) => {
+                    Self::update_vertex_buffer(gl, &mut buffers.vbos[0], uploaded_quad, &quad)?;
+                    Self::update_vertex_buffer(
+                        gl,
+                        &mut buffers.vbos[1],
+                        &self.uploaded,
+                        &instances,
+                    )?;
+                }
+                _ => {
+                    if let Some(buffers) = self.buffers.take() {
+                        buffers.delete(gl);
+                    }
+                    self.buffers = Some(Self::setup_buffers(
+                        gl,
+                        &handle.program(),
+                        BufferUsage::Stream,
+                        &Self::layout(),
+                        &[vertex_bytes(&quad), vertex_bytes(&instances)],
+                        &[0u32, 1, 2, 2, 3, 0],
+                    )?);
+                }
+            }
+            self.uploaded_quad = Some(quad);
+            self.uploaded = instances;
+        }
+        Ok(())
+    }
+
+    fn render(&mut self, gl: &glow::Context) {
+        if self.uploaded.is_empty() {
+            return;
+        }
+        let model = self.parent_matrix * self.transform.matrix();
+        if let Some(buffers) = &self.buffers {
+            unsafe {
+                if !self.shader.bind(gl, model) {
+                    return;
+                }
+                buffers.vao.bind(gl);
+                gl.draw_elements_instanced(
+                    glow::TRIANGLES,
+                    6,
+                    glow::UNSIGNED_INT,
+                    0,
+                    self.uploaded.len() as i32,
+                );
+            }
+        }
+    }
+
+    fn detach(&mut self, gl: &glow::Context, programs: &mut ProgramCache) {
+        unsafe {
+            self.shader.release(gl, programs);
+            if let Some(buffers) = self.buffers.take() {
+                buffers.delete(gl);
+            }
+        }
+        self.uploaded_quad = None;
+        self.uploaded.clear();
+    }
+
+    fn transform(&self) -> Option<&Transform> {
+        Some(&self.transform)
+    }
+
+    fn transform_mut(&mut self) -> Option<&mut Transform> {
+        Some(&mut self.transform)
+    }
+
+    fn set_parent_matrix(&mut self, matrix: Mat4) {
+        self.parent_matrix = matrix;
+    }
+
+    fn shader_source(&self) -> Option<&ShaderSource> {
+        Some(self.shader.source())
+    }
+
+    fn shader_defines(&self) -> &[(String, String)] {
+        self.shader.defines()
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        Some(self)
+    }
+
+    fn window_resize(&mut self, _draw_size: [f32; 2], _size: [f32; 2]) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use gfx_maths::Vec3;
+
+    use super::{Instance, InstanceData, InstancedRectangle};
+    use crate::transform::Transform;
+
+    #[test]
+    fn lays_out_instance_data_per_instance() {
+        let layout = InstancedRectangle::layout();
+        assert_eq!(layout.buffers.len(), 2);
+        assert_eq!(layout.buffers[0].divisor, 0);
+
+        let instances = &layout.buffers[1];
+        let attributes: Vec<(&str, i32, i32)> = instances
+            .attributes
+            .iter()
+            .map(|attribute| {
+                (
+                    attribute.name.as_str(),
+                    attribute.components,
+                    attribute.offset,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            attributes,
+            [
+                ("in_instance_model", 16, 0),
+                ("in_instance_color", 4, 64),
+                ("in_instance_uv_rect", 4, 80),
+            ]
+        );
+        assert_eq!(instances.stride, 96);
+        assert_eq!(instances.divisor, 1);
+    }
+
+    #[test]
+    fn fills_instance_data_from_the_instance() {
+        let transform = Transform::from_translation(Vec3::new(4.0, 2.0, 0.0));
+        let instance = Instance::new(transform)
+            .with_color([1.0, 0.0, 0.0, 0.5])
+            .with_uv_rect([0.5, 0.0, 0.5, 0.25]);
+
+        assert_eq!(
+            instance.data(),
+            InstanceData {
+                model: transform.matrix(),
+                color: [1.0, 0.0, 0.0, 0.5],
+                uv_rect: [0.5, 0.0, 0.5, 0.25],
+            }
+        );
+        assert_eq!(Instance::default().data().color, [1.0; 4]);
+        assert_eq!(Instance::default().data().uv_rect, [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn returns_the_index_of_pushed_instances() {
+        let mut rectangles = InstancedRectangle::new(16, 16, "resources/instanced.shader");
+
+        assert_eq!(rectangles.push(Instance::default()), 0);
+        assert_eq!(rectangles.push(Instance::default().with_color([0.0; 4])), 1);
+        assert_eq!(rectangles.instances[1].color, [0.0; 4]);
+    }
+}
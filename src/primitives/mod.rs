@@ -1,2 +1,3 @@
+pub mod instanced;
 pub mod rectangle;
 pub mod triangle;
@@ -1,41 +1,59 @@
+use crate::error::Error;
 use crate::object::BufferData;
-use crate::object::OpenGLObjectTrait;
-use crate::object::MVP;
-use gfx_maths::Mat4;
-use gfx_maths::Vec3;
+use crate::object::{OpenGLObjectTrait, ShaderSelection};
+use crate::program::cache::ProgramCache;
+use crate::resource::BufferUsage;
+use crate::shaders::ShaderSource;
+use crate::transform::Transform;
+use crate::vertex::Vertex2D;
+use gfx_maths::{Mat4, Vec3};
 use glow::HasContext;
-use glow::NativeProgram;
+use std::any::Any;
 
 #[derive(Debug)]
 pub struct Rectangle {
     pub width: u32,
     pub height: u32,
-    pub program: Option<Box<NativeProgram>>,
     buffers: Option<BufferData>,
-    source: String,
-    pub matrix: MVP,
+    /// Vertices last written to `buffers`.
+    uploaded: Option<[Vertex2D; 4]>,
+    /// The shader and the program drawn with it.
+    pub shader: ShaderSelection,
+    /// Applied through the `u_model` uniform; the view and projection come
+    /// from the `Camera` block.
+    pub transform: Transform,
+    /// World matrix of the scene node holding the rectangle.
+    parent_matrix: Mat4,
 }
 
 impl Rectangle {
-    pub fn new(width: u32, height: u32, source: &str) -> Self {
+    pub fn new(width: u32, height: u32, source: impl Into<ShaderSource>) -> Self {
         Self {
             height,
             width,
-            program: None,
             buffers: None,
-            source: source.to_string(),
-            matrix: MVP::new(800, 600),
+            uploaded: None,
+            shader: ShaderSelection::new(source),
+            transform: Transform::new(),
+            parent_matrix: Mat4::identity(),
         }
     }
+
+    /// Compiles the shader with `#define name value` lines injected, see
+    /// `ShaderDataBuilder::define`.
+    pub fn with_defines(mut self, defines: &[(&str, &str)]) -> Self {
+        self.shader.set_defines(defines);
+        self
+    }
 }
 
 impl Rectangle {
     pub fn is_in_bounding_box(&self, x: i32, y: i32) -> bool {
-        let data = self.matrix.view * self.matrix.model;
-        if x >= data.x as i32
-            && x as f32 <= data.x as f32 + self.width as f32
-            && y >= data.y as i32
-            && y as f32 <= data.y as f32 + self.height as f32
+        let data = self.transform.to_local(Vec3::new(x as f32, y as f32, 0.0));
+        if data.x >= 0.0
+            && data.x <= self.width as f32
+            && data.y >= 0.0
+            && data.y <= self.height as f32
         {
             return true;
         }
@@ -44,69 +62,103 @@ impl Rectangle {
 }
 
 impl OpenGLObjectTrait for Rectangle {
-    fn attach(&mut self, gl: &glow::Context) {
-        unsafe {
-            let program = gl.create_program().expect("Cannot create program");
+    fn attach(&mut self, gl: &glow::Context, programs: &mut ProgramCache) -> Result<(), Error> {
+        self.update(gl, programs)
+    }
 
-            Self::setup_shaders(gl, &program, self.source.clone());
-            gl.use_program(Some(program));
+    /// Rewrites the corners that moved when `width` or `height` changed.
+    fn update(&mut self, gl: &glow::Context, programs: &mut ProgramCache) -> Result<(), Error> {
+        unsafe {
+            let handle = self.shader.acquire(gl, programs, &mut self.uploaded)?;
 
+            let (width, height) = (self.width as f32, self.height as f32);
             let vertices = [
-                0f32,
-                0f32,
-                0f32,
-                self.height as f32,
-                self.width as f32,
-                self.height as f32,
-                self.width as f32,
-                0f32,
+                Vertex2D {
+                    in_position: [0.0, 0.0],
+                },
+                Vertex2D {
+                    in_position: [0.0, height],
+                },
+                Vertex2D {
+                    in_position: [width, height],
+                },
+                Vertex2D {
+                    in_position: [width, 0.0],
+                },
             ];
 
-            self.buffers = Some(Self::setup_buffers(
-                gl,
-                &vertices,
-                &vec![0u32, 1, 2, 2, 3],
-                2,
-                8,
-            ));
-
-            let matrix =
-                self.matrix.projection * self.matrix.view * Mat4::translate(self.matrix.model);
-
-            let proj_matrix = gl.get_uniform_location(program, "u_proj_matrix");
-
-            gl.uniform_matrix_4_f32_slice(proj_matrix.as_ref(), false, &matrix.values);
-
-            self.program = Some(Box::new(program));
+            match (self.buffers.as_mut(), self.uploaded.as_ref()) {
+                (Some(buffers), Some(uploaded)) => {
+                    Self::update_vertex_buffer(gl, &mut buffers.vbos[0], uploaded, &vertices)?;
+                }
+                _ => {
+                    if let Some(buffers) = self.buffers.take() {
+                        buffers.delete(gl);
+                    }
+                    self.buffers = Some(Self::setup_vertex_buffers(
+                        gl,
+                        &handle.program(),
+                        BufferUsage::Dynamic,
+                        &vertices,
+                        &[0u32, 1, 2, 2, 3, 0],
+                    )?);
+                }
+            }
+            self.uploaded = Some(vertices);
         }
+        Ok(())
     }
 
     fn render(&mut self, gl: &glow::Context) {
-        unsafe {
-            gl.draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_INT, 0);
+        let model = self.parent_matrix * self.transform.matrix();
+        if let Some(buffers) = &self.buffers {
+            unsafe {
+                if !self.shader.bind(gl, model) {
+                    return;
+                }
+                buffers.vao.bind(gl);
+                gl.draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_INT, 0);
+            }
         }
     }
 
-    fn detach(&mut self, gl: &glow::Context) {
-        if self.program.is_some() {
-            unsafe {
-                gl.delete_program(**self.program.as_ref().unwrap());
+    fn detach(&mut self, gl: &glow::Context, programs: &mut ProgramCache) {
+        unsafe {
+            self.shader.release(gl, programs);
+            if let Some(buffers) = self.buffers.take() {
+                buffers.delete(gl);
             }
-        };
+        }
+        self.uploaded = None;
     }
 
-    fn move_model(&mut self, movement_x: f32, movement_y: f32, movement_z: f32) {
-        self.matrix.model += Vec3::new(movement_x, movement_y, movement_z);
+    fn transform(&self) -> Option<&Transform> {
+        Some(&self.transform)
     }
 
-    fn set_model(&mut self, movement_x: f32, movement_y: f32, movement_z: f32) {
-        self.matrix.model = Vec3::new(movement_x, movement_y, movement_z);
+    fn transform_mut(&mut self) -> Option<&mut Transform> {
+        Some(&mut self.transform)
     }
 
-    fn window_resize(&mut self, _draw_size: [f32; 2], size: [f32; 2]) {
-        {
-            self.matrix.projection =
-                Mat4::orthographic_opengl(0.0, size[0] as f32, size[1] as f32, 0.0, -1.0, 1.0);
-        }
+    fn set_parent_matrix(&mut self, matrix: Mat4) {
+        self.parent_matrix = matrix;
     }
+
+    fn shader_source(&self) -> Option<&ShaderSource> {
+        Some(self.shader.source())
+    }
+
+    fn shader_defines(&self) -> &[(String, String)] {
+        self.shader.defines()
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        Some(self)
+    }
+
+    fn window_resize(&mut self, _draw_size: [f32; 2], _size: [f32; 2]) {}
 }
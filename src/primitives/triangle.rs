@@ -1,62 +1,142 @@
+use crate::error::Error;
 use crate::object::BufferData;
 use glow::HasContext;
-use glow::NativeProgram;
 
-use crate::object::OpenGLObjectTrait;
+use crate::object::{OpenGLObjectTrait, ShaderSelection};
+use crate::program::cache::ProgramCache;
+use crate::resource::BufferUsage;
+use crate::shaders::ShaderSource;
+use crate::transform::Transform;
+use crate::vertex::Vertex2D;
+use gfx_maths::Mat4;
+use std::any::Any;
 
 #[derive(Debug)]
 pub struct Triangle {
     positions: [f32; 6],
-    program: Option<Box<NativeProgram>>,
     buffers: Option<BufferData>,
-    source: String,
+    /// Vertices last written to `buffers`.
+    uploaded: Option<[Vertex2D; 3]>,
+    /// The shader and the program drawn with it.
+    pub shader: ShaderSelection,
+    /// Applied to `positions` through the `u_model` uniform.
+    pub transform: Transform,
+    /// World matrix of the scene node holding the triangle.
+    parent_matrix: Mat4,
 }
 
 impl Triangle {
-    pub fn new(positions: [f32; 6], source: &str) -> Self {
+    pub fn new(positions: [f32; 6], source: impl Into<ShaderSource>) -> Self {
         Self {
             positions,
-            program: None,
             buffers: None,
-            source: source.to_string(),
+            uploaded: None,
+            shader: ShaderSelection::new(source),
+            transform: Transform::new(),
+            parent_matrix: Mat4::identity(),
         }
     }
+
+    /// Compiles the shader with `#define name value` lines injected, see
+    /// `ShaderDataBuilder::define`.
+    pub fn with_defines(mut self, defines: &[(&str, &str)]) -> Self {
+        self.shader.set_defines(defines);
+        self
+    }
+
+    /// Moves the corners; the ones that changed are rewritten on the next
+    /// update.
+    pub fn set_positions(&mut self, positions: [f32; 6]) {
+        self.positions = positions;
+    }
 }
 
 impl OpenGLObjectTrait for Triangle {
-    fn attach(&mut self, gl: &glow::Context) {
+    fn attach(&mut self, gl: &glow::Context, programs: &mut ProgramCache) -> Result<(), Error> {
+        self.update(gl, programs)
+    }
+
+    fn update(&mut self, gl: &glow::Context, programs: &mut ProgramCache) -> Result<(), Error> {
         unsafe {
-            let program = gl.create_program().expect("Cannot create program");
-
-            Self::setup_shaders(gl, &program, self.source.clone());
-            gl.use_program(Some(program));
-            self.buffers = Some(Self::setup_buffers(
-                gl,
-                &self.positions,
-                &vec![0u32, 1, 2],
-                2,
-                8,
-            ));
+            let handle = self.shader.acquire(gl, programs, &mut self.uploaded)?;
+
+            let corner = |index: usize| Vertex2D {
+                in_position: [self.positions[index * 2], self.positions[index * 2 + 1]],
+            };
+            let vertices = [corner(0), corner(1), corner(2)];
+
+            match (self.buffers.as_mut(), self.uploaded.as_ref()) {
+                (Some(buffers), Some(uploaded)) => {
+                    Self::update_vertex_buffer(gl, &mut buffers.vbos[0], uploaded, &vertices)?;
+                }
+                _ => {
+                    if let Some(buffers) = self.buffers.take() {
+                        buffers.delete(gl);
+                    }
+                    self.buffers = Some(Self::setup_vertex_buffers(
+                        gl,
+                        &handle.program(),
+                        BufferUsage::Dynamic,
+                        &vertices,
+                        &[0u32, 1, 2],
+                    )?);
+                }
+            }
+            self.uploaded = Some(vertices);
         }
+        Ok(())
     }
 
     fn render(&mut self, gl: &glow::Context) {
-        unsafe {
-            gl.draw_elements(glow::TRIANGLES, 4, glow::UNSIGNED_INT, 0);
+        let model = self.parent_matrix * self.transform.matrix();
+        if let Some(buffers) = &self.buffers {
+            unsafe {
+                if !self.shader.bind(gl, model) {
+                    return;
+                }
+                buffers.vao.bind(gl);
+                gl.draw_elements(glow::TRIANGLES, 3, glow::UNSIGNED_INT, 0);
+            }
         }
     }
 
-    fn detach(&mut self, gl: &glow::Context) {
-        if self.program.is_some() {
-            unsafe {
-                gl.delete_program(**self.program.as_ref().unwrap());
+    fn detach(&mut self, gl: &glow::Context, programs: &mut ProgramCache) {
+        unsafe {
+            self.shader.release(gl, programs);
+            if let Some(buffers) = self.buffers.take() {
+                buffers.delete(gl);
             }
-        };
+        }
+        self.uploaded = None;
     }
 
-    fn move_model(&mut self, _movement_x: f32, _movement_y: f32, _movement_z: f32) {}
+    fn transform(&self) -> Option<&Transform> {
+        Some(&self.transform)
+    }
 
-    fn window_resize(&mut self, _draw_size: [f32; 2], _size: [f32; 2]) {}
+    fn transform_mut(&mut self) -> Option<&mut Transform> {
+        Some(&mut self.transform)
+    }
+
+    fn set_parent_matrix(&mut self, matrix: Mat4) {
+        self.parent_matrix = matrix;
+    }
+
+    fn shader_source(&self) -> Option<&ShaderSource> {
+        Some(self.shader.source())
+    }
 
-    fn set_model(&mut self, _movement_x: f32, _movement_y: f32, _movement_z: f32) {}
+    fn shader_defines(&self) -> &[(String, String)] {
+        self.shader.defines()
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        Some(self)
+    }
+
+    fn window_resize(&mut self, _draw_size: [f32; 2], _size: [f32; 2]) {}
 }
@@ -0,0 +1,291 @@
+use std::ffi::c_void;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use glow::{Context, HasContext, NativeProgram};
+
+use super::{create_program, link_shader_data};
+use crate::shaders::{ShaderData, ShaderError};
+
+const PROGRAM_BINARY_RETRIEVABLE_HINT: u32 = 0x8257;
+const PROGRAM_BINARY_LENGTH: u32 = 0x8741;
+const NUM_PROGRAM_BINARY_FORMATS: u32 = 0x87FE;
+
+type GetProgramBinary = unsafe extern "system" fn(u32, i32, *mut i32, *mut u32, *mut c_void);
+type ProgramBinary = unsafe extern "system" fn(u32, u32, *const c_void, i32);
+type GetProgramIv = unsafe extern "system" fn(u32, u32, *mut i32);
+type ProgramParameterI = unsafe extern "system" fn(u32, u32, i32);
+
+/// Program binary entry points, loaded from the window so a driver without
+/// them falls back to compiling.
+#[derive(Clone, Copy, Debug)]
+struct BinaryFunctions {
+    get_program_binary: GetProgramBinary,
+    program_binary: ProgramBinary,
+    get_program_iv: GetProgramIv,
+    program_parameter_i: ProgramParameterI,
+}
+
+/// Stores linked programs as `glGetProgramBinary` blobs under a directory,
+/// so later launches can skip compiling and linking.
+///
+/// Files are keyed by a hash of the preprocessed stages and the GL vendor,
+/// renderer and version, so a driver update starts from scratch. A binary
+/// the driver rejects is replaced by compiling from source.
+#[derive(Debug)]
+pub struct BinaryCache {
+    directory: PathBuf,
+    functions: Option<BinaryFunctions>,
+}
+
+/// FNV-1a, which unlike `DefaultHasher` gives the same key across Rust
+/// releases.
+fn hash(parts: &[&str]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// The file a program is stored in, named after the GL `driver` strings
+/// and the preprocessed source of its `stages`.
+fn file_name(driver: &[String], stages: &[&str]) -> String {
+    let mut parts: Vec<&str> = driver.iter().map(String::as_str).collect();
+    parts.extend(stages);
+    format!("{:016x}.bin", hash(&parts))
+}
+
+/// Lays out a cache file: the 4-byte little-endian binary format followed by
+/// the binary.
+fn encode(format: u32, binary: &[u8]) -> Vec<u8> {
+    let mut bytes = format.to_le_bytes().to_vec();
+    bytes.extend(binary);
+    bytes
+}
+
+/// Splits a cache file into its binary format and binary, `None` when it is
+/// too short to hold both.
+fn decode(bytes: &[u8]) -> Option<(u32, &[u8])> {
+    if bytes.len() <= 4 {
+        return None;
+    }
+    let (format, binary) = bytes.split_at(4);
+    Some((u32::from_le_bytes(format.try_into().ok()?), binary))
+}
+
+impl BinaryCache {
+    /// Creates a cache writing to `directory`. `loader` resolves GL entry
+    /// points, as `Window::load_with` does; when the driver lacks program
+    /// binaries every program is compiled from source.
+    ///
+    /// # Safety
+    /// The GL context `loader` resolves against must be current.
+    pub unsafe fn new(
+        directory: impl Into<PathBuf>,
+        mut loader: impl FnMut(&str) -> *const c_void,
+    ) -> Self {
+        let mut load = |name| {
+            let pointer = loader(name);
+            if pointer.is_null() {
+                None
+            } else {
+                Some(pointer)
+            }
+        };
+
+        let functions = (|| {
+            Some(BinaryFunctions {
+                get_program_binary: std::mem::transmute::<*const c_void, GetProgramBinary>(load(
+                    "glGetProgramBinary",
+                )?),
+                program_binary: std::mem::transmute::<*const c_void, ProgramBinary>(load(
+                    "glProgramBinary",
+                )?),
+                get_program_iv: std::mem::transmute::<*const c_void, GetProgramIv>(load(
+                    "glGetProgramiv",
+                )?),
+                program_parameter_i: std::mem::transmute::<*const c_void, ProgramParameterI>(load(
+                    "glProgramParameteri",
+                )?),
+            })
+        })();
+
+        Self {
+            directory: directory.into(),
+            functions,
+        }
+    }
+
+    /// Whether programs are actually stored; `false` when the driver has no
+    /// binary formats.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn is_supported(&self, gl: &Context) -> bool {
+        self.functions.is_some() && gl.get_parameter_i32(NUM_PROGRAM_BINARY_FORMATS) > 0
+    }
+
+    unsafe fn path(&self, gl: &Context, shader_data: &ShaderData) -> PathBuf {
+        let driver = [
+            gl.get_parameter_string(glow::VENDOR),
+            gl.get_parameter_string(glow::RENDERER),
+            gl.get_parameter_string(glow::VERSION),
+        ];
+        let stages: Vec<&str> = shader_data
+            .stages
+            .iter()
+            .map(|stage| stage.source.as_str())
+            .collect();
+
+        self.directory.join(file_name(&driver, &stages))
+    }
+
+    /// Links `shader_data` from a stored binary, or compiles it and stores
+    /// the result.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn link(
+        &self,
+        gl: &Context,
+        shader_data: &ShaderData,
+    ) -> Result<NativeProgram, ShaderError> {
+        let functions = match self.functions {
+            Some(functions) if self.is_supported(gl) => functions,
+            _ => return Self::compile(gl, shader_data, None),
+        };
+
+        let path = self.path(gl, shader_data);
+        if let Some(program) = Self::load(gl, functions, &path) {
+            return Ok(program);
+        }
+
+        let program = Self::compile(gl, shader_data, Some(functions))?;
+        if let Err(error) = self.store(functions, program, &path) {
+            eprintln!("cannot store program binary {}: {}", path.display(), error);
+        }
+        Ok(program)
+    }
+
+    unsafe fn compile(
+        gl: &Context,
+        shader_data: &ShaderData,
+        functions: Option<BinaryFunctions>,
+    ) -> Result<NativeProgram, ShaderError> {
+        let program = create_program(gl, shader_data)?;
+        if let Some(functions) = functions {
+            (functions.program_parameter_i)(program.0.get(), PROGRAM_BINARY_RETRIEVABLE_HINT, 1);
+        }
+
+        match link_shader_data(gl, &program, shader_data) {
+            Ok(()) => Ok(program),
+            Err(error) => {
+                gl.delete_program(program);
+                Err(error)
+            }
+        }
+    }
+
+    /// Reads the binary stored at `path`, returning `None` when it is missing
+    /// or rejected.
+    unsafe fn load(gl: &Context, functions: BinaryFunctions, path: &Path) -> Option<NativeProgram> {
+        let bytes = fs::read(path).ok()?;
+        let (format, binary) = decode(&bytes)?;
+
+        let program = gl.create_program().ok()?;
+        (functions.program_binary)(
+            program.0.get(),
+            format,
+            binary.as_ptr() as *const c_void,
+            binary.len() as i32,
+        );
+
+        if gl.get_program_link_status(program) {
+            Some(program)
+        } else {
+            gl.delete_program(program);
+            None
+        }
+    }
+
+    unsafe fn store(
+        &self,
+        functions: BinaryFunctions,
+        program: NativeProgram,
+        path: &Path,
+    ) -> std::io::Result<()> {
+        let name = program.0.get();
+
+        let mut length = 0;
+        (functions.get_program_iv)(name, PROGRAM_BINARY_LENGTH, &mut length);
+        if length <= 0 {
+            return Ok(());
+        }
+
+        let mut binary = vec![0u8; length as usize];
+        let mut written = 0;
+        let mut format = 0;
+        (functions.get_program_binary)(
+            name,
+            length,
+            &mut written,
+            &mut format,
+            binary.as_mut_ptr() as *mut c_void,
+        );
+        binary.truncate(written.max(0) as usize);
+
+        fs::create_dir_all(&self.directory)?;
+        fs::write(path, encode(format, &binary))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, file_name, hash};
+
+    fn driver(version: &str) -> Vec<String> {
+        vec![
+            "Vendor".to_string(),
+            "Renderer".to_string(),
+            version.to_string(),
+        ]
+    }
+
+    #[test]
+    fn hashes_like_fnv_1a() {
+        assert_eq!(hash(&[]), 0xcbf2_9ce4_8422_2325);
+        // FNV-1a of "a\0", parts end in a zero byte
+        assert_eq!(hash(&["a"]), 0x089b_e207_b544_f1e4);
+        assert_ne!(hash(&["ab", "c"]), hash(&["a", "bc"]));
+    }
+
+    #[test]
+    fn names_files_after_the_driver_and_the_stages() {
+        let stages = ["void main() {}", "out vec4 color; void main() {}"];
+        let name = file_name(&driver("4.6"), &stages);
+
+        assert_eq!(name.len(), "0123456789abcdef.bin".len());
+        assert!(name.ends_with(".bin"));
+        assert_eq!(name, file_name(&driver("4.6"), &stages));
+        assert_ne!(name, file_name(&driver("4.5"), &stages));
+        assert_ne!(name, file_name(&driver("4.6"), &stages[..1]));
+    }
+
+    #[test]
+    fn stores_the_format_before_the_binary() {
+        let bytes = encode(0x8E21, &[1, 2, 3]);
+
+        assert_eq!(bytes, [0x21, 0x8E, 0, 0, 1, 2, 3]);
+        assert_eq!(decode(&bytes), Some((0x8E21, &[1, 2, 3][..])));
+    }
+
+    #[test]
+    fn rejects_files_without_a_binary() {
+        assert_eq!(decode(&[]), None);
+        assert_eq!(decode(&encode(0x8E21, &[])), None);
+    }
+}
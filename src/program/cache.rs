@@ -0,0 +1,309 @@
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use glow::{Context, HasContext};
+
+use super::binary::BinaryCache;
+use super::{load_shader_data, Program};
+use crate::resource;
+use crate::shaders::{ShaderError, ShaderSource};
+
+/// Shared, reference-counted access to a program owned by a `ProgramCache`.
+#[derive(Clone, Debug)]
+pub struct ProgramHandle {
+    key: CacheKey,
+    shared: Rc<SharedProgram>,
+}
+
+impl ProgramHandle {
+    /// The program, which may be replaced by `ProgramCache::reload` between
+    /// frames.
+    pub fn program(&self) -> Ref<'_, Program> {
+        self.shared.program.borrow()
+    }
+
+    /// How many times the program was replaced by `ProgramCache::reload`.
+    /// Vertex arrays built for another generation may point at attribute
+    /// locations the program no longer uses.
+    pub fn generation(&self) -> u64 {
+        self.shared.generation.get()
+    }
+}
+
+/// A program and the generation of it, shared by the cache and every
+/// handle.
+#[derive(Debug)]
+struct SharedProgram {
+    program: RefCell<Program>,
+    generation: Cell<u64>,
+}
+
+#[derive(Debug)]
+struct CachedProgram {
+    source: ShaderSource,
+    shared: Rc<SharedProgram>,
+}
+
+/// Compiles every `.shader` file once per set of defines and shares the
+/// program between all objects using it.
+#[derive(Debug, Default)]
+pub struct ProgramCache {
+    programs: HashMap<CacheKey, CachedProgram>,
+    binary_cache: Option<BinaryCache>,
+}
+
+/// Files are identified by their canonical path, so `./a.shader` and
+/// `a.shader` share a program. In-memory sources are identified by name and
+/// a hash of their text, so two sources sharing a name do not share a
+/// program.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum SourceKey {
+    File(PathBuf),
+    Memory { name: String, hash: u64 },
+}
+
+impl SourceKey {
+    fn new(source: &ShaderSource) -> Self {
+        match source {
+            ShaderSource::File(path) => {
+                SourceKey::File(fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path)))
+            }
+            ShaderSource::Static { name, source } => SourceKey::memory(name, source),
+            ShaderSource::Owned { name, source } => SourceKey::memory(name, source),
+        }
+    }
+
+    fn memory(name: &str, source: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+        SourceKey::Memory {
+            name: name.to_string(),
+            hash: hasher.finish(),
+        }
+    }
+}
+
+/// One permutation of a source. Defines are sorted, so the order they are
+/// passed in does not matter.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct CacheKey {
+    source: SourceKey,
+    defines: Vec<(String, String)>,
+}
+
+impl CacheKey {
+    fn new<N: AsRef<str>, V: AsRef<str>>(source: &ShaderSource, defines: &[(N, V)]) -> Self {
+        let mut sorted: Vec<(String, String)> = vec![];
+        for (name, value) in defines {
+            let name = name.as_ref().to_string();
+            sorted.retain(|(defined, _)| *defined != name);
+            sorted.push((name, value.as_ref().to_string()));
+        }
+        sorted.sort();
+
+        Self {
+            source: SourceKey::new(source),
+            defines: sorted,
+        }
+    }
+}
+
+/// Compiles one permutation, going through the binary cache when there is
+/// one.
+unsafe fn compile(
+    gl: &Context,
+    binary_cache: Option<&BinaryCache>,
+    source: &ShaderSource,
+    defines: &[(String, String)],
+) -> Result<Program, ShaderError> {
+    let shader_data = load_shader_data(gl, source, defines)?;
+    Program::from_shader_data(gl, &shader_data, binary_cache)
+}
+
+impl ProgramCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores linked programs on disk, so later launches can skip
+    /// compiling. Off by default.
+    pub fn set_binary_cache(&mut self, binary_cache: Option<BinaryCache>) {
+        self.binary_cache = binary_cache;
+    }
+
+    /// Returns a handle to the program for `source`, compiling it on first
+    /// use.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn get(
+        &mut self,
+        gl: &Context,
+        source: impl Into<ShaderSource>,
+    ) -> Result<ProgramHandle, ShaderError> {
+        self.get_permutation::<&str, &str>(gl, source, &[])
+    }
+
+    /// Returns a handle to the program for `source` compiled with `defines`
+    /// injected after its version line. Every set of defines is compiled
+    /// and cached separately.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn get_permutation<N: AsRef<str>, V: AsRef<str>>(
+        &mut self,
+        gl: &Context,
+        source: impl Into<ShaderSource>,
+        defines: &[(N, V)],
+    ) -> Result<ProgramHandle, ShaderError> {
+        let source = source.into();
+        let key = CacheKey::new(&source, defines);
+
+        if let Some(cached) = self.programs.get(&key) {
+            return Ok(ProgramHandle {
+                key,
+                shared: cached.shared.clone(),
+            });
+        }
+
+        let program = compile(gl, self.binary_cache.as_ref(), &source, &key.defines)?;
+        let shared = Rc::new(SharedProgram {
+            program: RefCell::new(program),
+            generation: Cell::new(0),
+        });
+        self.programs.insert(
+            key.clone(),
+            CachedProgram {
+                source,
+                shared: shared.clone(),
+            },
+        );
+
+        Ok(ProgramHandle { key, shared })
+    }
+
+    /// Gives a handle back, deleting the program once no object uses it.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn release(&mut self, gl: &Context, handle: ProgramHandle) {
+        let ProgramHandle { key, shared } = handle;
+        drop(shared);
+
+        let unused = self
+            .programs
+            .get(&key)
+            .is_some_and(|cached| Rc::strong_count(&cached.shared) == 1);
+
+        if unused {
+            if let Some(cached) = self.programs.remove(&key) {
+                if let Ok(shared) = Rc::try_unwrap(cached.shared) {
+                    shared.program.into_inner().delete(gl);
+                }
+            }
+        }
+    }
+
+    /// The source and defines of every cached program.
+    pub fn permutations(&self) -> impl Iterator<Item = (&ShaderSource, &[(String, String)])> {
+        self.programs
+            .iter()
+            .map(|(key, cached)| (&cached.source, key.defines.as_slice()))
+    }
+
+    /// Rebuilds every permutation of `source` in place, so every handle sees
+    /// the new program, and bumps its generation. A permutation that fails
+    /// keeps its previous program and the first error is returned.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn reload(
+        &mut self,
+        gl: &Context,
+        source: impl Into<ShaderSource>,
+    ) -> Result<(), ShaderError> {
+        let source = SourceKey::new(&source.into());
+        let mut result = Ok(());
+
+        for (key, cached) in self.programs.iter() {
+            if key.source != source {
+                continue;
+            }
+
+            match compile(gl, self.binary_cache.as_ref(), &cached.source, &key.defines) {
+                Ok(program) => {
+                    cached.shared.program.replace(program).delete(gl);
+                    let generation = &cached.shared.generation;
+                    generation.set(generation.get() + 1);
+                }
+                Err(error) if result.is_ok() => result = Err(error),
+                Err(_) => {}
+            }
+        }
+
+        result
+    }
+
+    /// Logs, once per program, the uniforms that differ between the source
+    /// and what was set from code since the last call. Call it at the end of
+    /// every frame.
+    pub fn report_uniforms(&self) {
+        for cached in self.programs.values() {
+            let issues = cached.shared.program.borrow().check_uniforms_once();
+            for issue in issues.unwrap_or_default() {
+                eprintln!("{}: {}", cached.source.name(), issue);
+            }
+        }
+    }
+
+    /// Deletes every program, whether or not handles are still alive.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn clear(&mut self, gl: &Context) {
+        for (_, cached) in self.programs.drain() {
+            let native = cached.shared.program.borrow().native();
+            gl.delete_program(native);
+            resource::deleted(native);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CacheKey, SourceKey};
+    use crate::shaders::ShaderSource;
+
+    #[test]
+    fn keys_memory_sources_by_name_and_text() {
+        let a = ShaderSource::owned("a", "void main() {}".to_string());
+        let same = ShaderSource::embedded("a", "void main() {}");
+        let changed = ShaderSource::owned("a", "void main() { }".to_string());
+        let renamed = ShaderSource::owned("b", "void main() {}".to_string());
+
+        assert_eq!(SourceKey::new(&a), SourceKey::new(&same));
+        assert_ne!(SourceKey::new(&a), SourceKey::new(&changed));
+        assert_ne!(SourceKey::new(&a), SourceKey::new(&renamed));
+    }
+
+    #[test]
+    fn sorts_defines_keeping_the_last_value() {
+        let source = ShaderSource::file("missing.shader");
+        let key = CacheKey::new(&source, &[("B", "1"), ("A", ""), ("B", "2")]);
+        let reordered = CacheKey::new(&source, &[("A", ""), ("B", "2")]);
+
+        assert_eq!(key, reordered);
+        assert_eq!(
+            key.defines,
+            [
+                ("A".to_string(), String::new()),
+                ("B".to_string(), "2".to_string())
+            ]
+        );
+    }
+}
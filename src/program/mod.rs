@@ -0,0 +1,362 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use glow::{Context, HasContext, NativeProgram};
+
+use crate::camera::{CAMERA_BINDING, CAMERA_BLOCK};
+use crate::resource;
+use crate::shaders::diagnostic::ShaderDiagnostic;
+use crate::shaders::version::GlslProfile;
+use crate::shaders::{ShaderData, ShaderError, ShaderSource};
+
+use self::binary::BinaryCache;
+use self::reflection::ProgramReflection;
+use self::uniform::{Uniform, UniformError};
+use self::usage::{UniformIssue, UniformUsage};
+
+pub mod binary;
+pub mod cache;
+pub mod reflection;
+pub mod uniform;
+pub mod usage;
+
+/// A linked program together with its reflection data, so uniforms can be
+/// set by name without querying their locations every frame.
+#[derive(Debug)]
+pub struct Program {
+    native: NativeProgram,
+    reflection: ProgramReflection,
+    /// Index into `reflection.uniforms` for every name a uniform answers to.
+    uniforms: HashMap<String, usize>,
+    /// Plain uniforms declared in the source, empty when it is unknown.
+    declared: Vec<String>,
+    /// Uniforms set since the last `check_uniforms`.
+    usage: RefCell<UniformUsage>,
+}
+
+impl Program {
+    /// Compiles and links a `.shader` file.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn new(
+        gl: &Context,
+        source: impl Into<ShaderSource>,
+    ) -> Result<Program, ShaderError> {
+        Program::with_defines::<&str, &str>(gl, source, &[])
+    }
+
+    /// Compiles and links a `.shader` file with `defines` injected after its
+    /// version line, see `ShaderDataBuilder::define`.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn with_defines<N: AsRef<str>, V: AsRef<str>>(
+        gl: &Context,
+        source: impl Into<ShaderSource>,
+        defines: &[(N, V)],
+    ) -> Result<Program, ShaderError> {
+        let shader_data = load_shader_data(gl, source, defines)?;
+        Program::from_shader_data(gl, &shader_data, None)
+    }
+
+    /// Links the stages of `shader_data`, reusing a binary stored by
+    /// `binary_cache` when the driver accepts it.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn from_shader_data(
+        gl: &Context,
+        shader_data: &ShaderData,
+        binary_cache: Option<&BinaryCache>,
+    ) -> Result<Program, ShaderError> {
+        let native = match binary_cache {
+            Some(binary_cache) => binary_cache.link(gl, shader_data)?,
+            None => {
+                let native = create_program(gl, shader_data)?;
+                if let Err(error) = link_shader_data(gl, &native, shader_data) {
+                    gl.delete_program(native);
+                    return Err(error);
+                }
+                native
+            }
+        };
+
+        let mut program = Program::from_native(gl, native);
+        program.declared = shader_data.declared_uniforms();
+        Ok(program)
+    }
+
+    /// Wraps an already linked program.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread and `native` must be linked.
+    #[track_caller]
+    pub unsafe fn from_native(gl: &Context, native: NativeProgram) -> Program {
+        resource::created(native);
+        let mut reflection = ProgramReflection::new(gl, native);
+
+        // The render loop writes the camera to one binding for every program
+        for block in reflection.uniform_blocks.iter_mut() {
+            if block.name == CAMERA_BLOCK {
+                gl.uniform_block_binding(native, block.index, CAMERA_BINDING);
+                block.binding = CAMERA_BINDING as i32;
+            }
+        }
+
+        let mut uniforms = HashMap::new();
+        for (index, uniform) in reflection.uniforms.iter().enumerate() {
+            if uniform.block.is_some() {
+                continue;
+            }
+            if let Some(base) = uniform.name.strip_suffix("[0]") {
+                uniforms.insert(base.to_string(), index);
+            }
+            uniforms.insert(uniform.name.clone(), index);
+        }
+
+        Program {
+            native,
+            reflection,
+            uniforms,
+            declared: vec![],
+            usage: RefCell::default(),
+        }
+    }
+
+    pub fn native(&self) -> NativeProgram {
+        self.native
+    }
+
+    pub fn reflection(&self) -> &ProgramReflection {
+        &self.reflection
+    }
+
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn bind(&self, gl: &Context) {
+        gl.use_program(Some(self.native));
+        self.usage.borrow_mut().bound = true;
+    }
+
+    /// Sets a uniform of this program, which must be bound.
+    ///
+    /// Values whose type does not match the GLSL declaration are rejected.
+    /// Unknown names are an error in debug builds and ignored in release
+    /// builds, where the driver may have optimized the uniform away.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn set_uniform<T: Into<Uniform>>(
+        &self,
+        gl: &Context,
+        name: &str,
+        value: T,
+    ) -> Result<(), UniformError> {
+        let value = value.into();
+
+        let mut usage = self.usage.borrow_mut();
+
+        let uniform = self
+            .uniforms
+            .get(name)
+            .map(|index| &self.reflection.uniforms[*index]);
+        if let Some(uniform) = uniform {
+            usage.set(&uniform.name);
+        }
+
+        match value.check(name, uniform) {
+            Ok(uniform) => {
+                value.upload(gl, uniform.location.as_ref());
+                Ok(())
+            }
+            Err(error @ UniformError::Unknown { .. }) => {
+                usage.unknown(name);
+                if cfg!(debug_assertions) {
+                    return Err(error);
+                }
+                Ok(())
+            }
+            Err(UniformError::TypeMismatch {
+                name,
+                expected,
+                found,
+            }) => {
+                usage.mismatched(UniformIssue::TypeMismatch {
+                    name: name.clone(),
+                    expected,
+                    found,
+                });
+                Err(UniformError::TypeMismatch {
+                    name,
+                    expected,
+                    found,
+                })
+            }
+        }
+    }
+
+    /// Compares the uniforms set since the last call with the active and
+    /// declared ones, then starts recording again. Returns `None` when the
+    /// program was not bound in between.
+    pub fn check_uniforms(&self) -> Option<Vec<UniformIssue>> {
+        let mut usage = self.usage.borrow_mut();
+        let issues = if usage.bound {
+            Some(usage.issues(&self.reflection, &self.declared))
+        } else {
+            None
+        };
+        usage.clear();
+        issues
+    }
+
+    /// Like `check_uniforms`, but only returns issues the first time the
+    /// program was bound, so they can be logged once.
+    pub fn check_uniforms_once(&self) -> Option<Vec<UniformIssue>> {
+        if self.usage.borrow().reported {
+            self.usage.borrow_mut().clear();
+            return None;
+        }
+        let issues = self.check_uniforms()?;
+        self.usage.borrow_mut().reported = true;
+        Some(issues)
+    }
+
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn delete(self, gl: &Context) {
+        gl.delete_program(self.native);
+        resource::deleted(self.native);
+    }
+}
+
+/// Compiles the stages of the `.shader` file `source` and links them into
+/// `program`, returning driver errors instead of panicking.
+///
+/// # Safety
+/// `gl` must be current on this thread and `program` must belong to it.
+pub unsafe fn setup_shaders(
+    gl: &Context,
+    program: &NativeProgram,
+    source: impl Into<ShaderSource>,
+) -> Result<(), ShaderError> {
+    setup_shaders_with_defines::<&str, &str>(gl, program, source, &[])
+}
+
+/// Like `setup_shaders`, with `defines` injected after the version line of
+/// every stage.
+///
+/// # Safety
+/// `gl` must be current on this thread and `program` must belong to it.
+pub unsafe fn setup_shaders_with_defines<N: AsRef<str>, V: AsRef<str>>(
+    gl: &Context,
+    program: &NativeProgram,
+    source: impl Into<ShaderSource>,
+    defines: &[(N, V)],
+) -> Result<(), ShaderError> {
+    let shader_data = load_shader_data(gl, source, defines)?;
+    link_shader_data(gl, program, &shader_data)
+}
+
+/// Splits and preprocesses `source` for the profile of `gl`, failing when
+/// `gl` cannot compile its GLSL version.
+///
+/// # Safety
+/// `gl` must be current on this thread.
+pub unsafe fn load_shader_data<N: AsRef<str>, V: AsRef<str>>(
+    gl: &Context,
+    source: impl Into<ShaderSource>,
+    defines: &[(N, V)],
+) -> Result<ShaderData, ShaderError> {
+    let mut builder = ShaderData::builder(source).defines(defines);
+    if gl.version().is_embedded {
+        builder = builder.profile(GlslProfile::Es);
+    }
+    let shader_data = builder.build()?;
+    shader_data.check_context(gl)?;
+    Ok(shader_data)
+}
+
+/// Creates an empty program object for `shader_data`.
+///
+/// # Safety
+/// `gl` must be current on this thread.
+pub(crate) unsafe fn create_program(
+    gl: &Context,
+    shader_data: &ShaderData,
+) -> Result<NativeProgram, ShaderError> {
+    gl.create_program().map_err(|error| ShaderError::Create {
+        path: PathBuf::from(&shader_data.source_path),
+        error,
+    })
+}
+
+/// Compiles the stages of `shader_data` and links them into `program`.
+///
+/// # Safety
+/// `gl` must be current on this thread and `program` must belong to it.
+pub unsafe fn link_shader_data(
+    gl: &Context,
+    program: &NativeProgram,
+    shader_data: &ShaderData,
+) -> Result<(), ShaderError> {
+    let mut shaders = Vec::with_capacity(shader_data.stages.len());
+    let mut result = Ok(());
+
+    for stage in shader_data.stages.iter() {
+        let shader_type = stage
+            .shader_type
+            .gl_type()
+            .expect("ShaderData only holds real stages");
+        let shader = match gl.create_shader(shader_type) {
+            Ok(shader) => shader,
+            Err(error) => {
+                result = Err(ShaderError::Create {
+                    path: PathBuf::from(&shader_data.source_path),
+                    error,
+                });
+                break;
+            }
+        };
+
+        gl.shader_source(shader, &stage.source);
+
+        gl.compile_shader(shader);
+
+        if !gl.get_shader_compile_status(shader) {
+            result = Err(ShaderError::Compile {
+                path: PathBuf::from(&shader_data.source_path),
+                diagnostics: ShaderDiagnostic::parse_log(
+                    &gl.get_shader_info_log(shader),
+                    shader_data.source_path.as_ref(),
+                    &stage.line_map,
+                ),
+            });
+            gl.delete_shader(shader);
+            break;
+        }
+
+        gl.attach_shader(*program, shader);
+
+        shaders.push(shader);
+    }
+
+    if result.is_ok() {
+        gl.link_program(*program);
+
+        if !gl.get_program_link_status(*program) {
+            result = Err(ShaderError::Link {
+                path: PathBuf::from(&shader_data.source_path),
+                log: gl.get_program_info_log(*program),
+            });
+        }
+    }
+
+    for shader in shaders {
+        gl.detach_shader(*program, shader);
+        gl.delete_shader(shader);
+    }
+
+    result
+}
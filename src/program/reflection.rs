@@ -0,0 +1,423 @@
+use std::fmt;
+
+use glow::{Context, HasContext, NativeProgram, UniformLocation};
+
+/// An active uniform of a linked program.
+#[derive(Debug)]
+pub struct UniformInfo {
+    /// Name as reported by the driver; arrays end in `[0]`.
+    pub name: String,
+    /// GL type such as `glow::FLOAT_MAT4`.
+    pub gl_type: u32,
+    /// Number of array elements, 1 for non-arrays.
+    pub size: i32,
+    /// `None` for members of uniform blocks.
+    pub location: Option<UniformLocation>,
+    /// Index into `ProgramReflection::uniform_blocks` for block members.
+    pub block: Option<usize>,
+}
+
+/// An active uniform block of a linked program.
+#[derive(Debug)]
+pub struct UniformBlockInfo {
+    pub name: String,
+    pub index: u32,
+    pub binding: i32,
+    /// Size of the block's buffer storage in bytes.
+    pub data_size: i32,
+}
+
+/// An active vertex input of a linked program.
+#[derive(Debug)]
+pub struct AttributeInfo {
+    pub name: String,
+    pub gl_type: u32,
+    pub size: i32,
+    pub location: Option<u32>,
+}
+
+/// Everything a linked program exposes to the Rust side.
+#[derive(Debug, Default)]
+pub struct ProgramReflection {
+    pub uniforms: Vec<UniformInfo>,
+    pub uniform_blocks: Vec<UniformBlockInfo>,
+    pub attributes: Vec<AttributeInfo>,
+}
+
+/// A difference between the vertex data an object provides and the vertex
+/// inputs of its program.
+#[derive(Debug, PartialEq, Eq)]
+pub enum VertexInputMismatch {
+    /// The program reads an input the object does not provide.
+    Missing { name: String },
+    /// Both sides know the input but disagree on its component count.
+    Components {
+        name: String,
+        provided: i32,
+        expected: i32,
+    },
+}
+
+impl fmt::Display for VertexInputMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VertexInputMismatch::Missing { name } => {
+                write!(f, "vertex input {} is not provided", name)
+            }
+            VertexInputMismatch::Components {
+                name,
+                provided,
+                expected,
+            } => write!(
+                f,
+                "vertex input {} has {} components, the shader expects {}",
+                name, provided, expected
+            ),
+        }
+    }
+}
+
+impl ProgramReflection {
+    /// Queries the active uniforms, uniform blocks and attributes of a
+    /// linked program.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread and `program` must be linked.
+    pub unsafe fn new(gl: &Context, program: NativeProgram) -> Self {
+        let uniform_blocks = Self::uniform_blocks(gl, program);
+
+        let mut block_members = vec![];
+        for (block, info) in uniform_blocks.iter().enumerate() {
+            let count = gl.get_active_uniform_block_parameter_i32(
+                program,
+                info.index,
+                glow::UNIFORM_BLOCK_ACTIVE_UNIFORMS,
+            );
+            let mut indices = vec![0; count.max(0) as usize];
+            gl.get_active_uniform_block_parameter_i32_slice(
+                program,
+                info.index,
+                glow::UNIFORM_BLOCK_ACTIVE_UNIFORM_INDICES,
+                &mut indices,
+            );
+            block_members.extend(indices.into_iter().map(|index| (index as u32, block)));
+        }
+
+        let uniforms = (0..gl.get_active_uniforms(program))
+            .filter_map(|index| {
+                let active = gl.get_active_uniform(program, index)?;
+                let block = block_members
+                    .iter()
+                    .find(|(member, _)| *member == index)
+                    .map(|(_, block)| *block);
+                let location = match block {
+                    Some(_) => None,
+                    None => gl.get_uniform_location(program, &active.name),
+                };
+                Some(UniformInfo {
+                    name: active.name,
+                    gl_type: active.utype,
+                    size: active.size,
+                    location,
+                    block,
+                })
+            })
+            .collect();
+
+        let attributes = (0..gl.get_active_attributes(program))
+            .filter_map(|index| {
+                let active = gl.get_active_attribute(program, index)?;
+                let location = gl.get_attrib_location(program, &active.name);
+                Some(AttributeInfo {
+                    name: active.name,
+                    gl_type: active.atype,
+                    size: active.size,
+                    location,
+                })
+            })
+            .collect();
+
+        Self {
+            uniforms,
+            uniform_blocks,
+            attributes,
+        }
+    }
+
+    /// Uniform blocks need GL 3.1 or GLES 3.0; older contexts have none.
+    unsafe fn uniform_blocks(gl: &Context, program: NativeProgram) -> Vec<UniformBlockInfo> {
+        let version = gl.version();
+        let supported = if version.is_embedded {
+            version.major >= 3
+        } else {
+            (version.major, version.minor) >= (3, 1)
+        };
+        if !supported {
+            return vec![];
+        }
+
+        let count = gl.get_program_parameter_i32(program, glow::ACTIVE_UNIFORM_BLOCKS);
+        (0..count.max(0) as u32)
+            .map(|index| UniformBlockInfo {
+                name: gl.get_active_uniform_block_name(program, index),
+                index,
+                binding: gl.get_active_uniform_block_parameter_i32(
+                    program,
+                    index,
+                    glow::UNIFORM_BLOCK_BINDING,
+                ),
+                data_size: gl.get_active_uniform_block_parameter_i32(
+                    program,
+                    index,
+                    glow::UNIFORM_BLOCK_DATA_SIZE,
+                ),
+            })
+            .collect()
+    }
+
+    /// Finds a uniform by name; arrays can be named with or without `[0]`.
+    pub fn uniform(&self, name: &str) -> Option<&UniformInfo> {
+        self.uniforms
+            .iter()
+            .find(|uniform| uniform.name == name || uniform.name.strip_suffix("[0]") == Some(name))
+    }
+
+    pub fn uniform_block(&self, name: &str) -> Option<&UniformBlockInfo> {
+        self.uniform_blocks.iter().find(|block| block.name == name)
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&AttributeInfo> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.name == name)
+    }
+
+    /// Compares the vertex inputs an object provides, as `(name, components)`
+    /// pairs, with the attributes the program reads.
+    pub fn check_vertex_inputs(&self, provided: &[(&str, i32)]) -> Vec<VertexInputMismatch> {
+        self.attributes
+            .iter()
+            .filter(|attribute| !attribute.name.starts_with("gl_"))
+            .filter_map(|attribute| {
+                let expected = type_components(attribute.gl_type);
+                match provided.iter().find(|(name, _)| *name == attribute.name) {
+                    None => Some(VertexInputMismatch::Missing {
+                        name: attribute.name.clone(),
+                    }),
+                    Some((_, components)) if *components != expected => {
+                        Some(VertexInputMismatch::Components {
+                            name: attribute.name.clone(),
+                            provided: *components,
+                            expected,
+                        })
+                    }
+                    Some(_) => None,
+                }
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for ProgramReflection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for attribute in self.attributes.iter() {
+            write!(f, "in {} {}", type_name(attribute.gl_type), attribute.name)?;
+            if let Some(location) = attribute.location {
+                write!(f, " (location {})", location)?;
+            }
+            writeln!(f)?;
+        }
+        for block in self.uniform_blocks.iter() {
+            writeln!(
+                f,
+                "uniform block {} (binding {}, {} bytes)",
+                block.name, block.binding, block.data_size
+            )?;
+        }
+        for uniform in self.uniforms.iter() {
+            write!(f, "uniform {} {}", type_name(uniform.gl_type), uniform.name)?;
+            if uniform.size > 1 {
+                write!(f, " [{}]", uniform.size)?;
+            }
+            if let Some(block) = uniform.block {
+                write!(f, " (in {})", self.uniform_blocks[block].name)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Number of scalar components of a GL data type, e.g. 4 for `vec4` and 16
+/// for `mat4`.
+pub fn type_components(gl_type: u32) -> i32 {
+    match gl_type {
+        glow::FLOAT_VEC2 | glow::INT_VEC2 | glow::UNSIGNED_INT_VEC2 | glow::BOOL_VEC2 => 2,
+        glow::FLOAT_VEC3 | glow::INT_VEC3 | glow::UNSIGNED_INT_VEC3 | glow::BOOL_VEC3 => 3,
+        glow::FLOAT_VEC4 | glow::INT_VEC4 | glow::UNSIGNED_INT_VEC4 | glow::BOOL_VEC4 => 4,
+        glow::FLOAT_MAT2 => 4,
+        glow::FLOAT_MAT3 => 9,
+        glow::FLOAT_MAT4 => 16,
+        glow::FLOAT_MAT2x3 | glow::FLOAT_MAT3x2 => 6,
+        glow::FLOAT_MAT2x4 | glow::FLOAT_MAT4x2 => 8,
+        glow::FLOAT_MAT3x4 | glow::FLOAT_MAT4x3 => 12,
+        _ => 1,
+    }
+}
+
+/// Number of columns of a GL matrix type, each of which takes a vertex
+/// attribute location; 1 for other types.
+pub fn matrix_columns(gl_type: u32) -> i32 {
+    match gl_type {
+        glow::FLOAT_MAT2 | glow::FLOAT_MAT2x3 | glow::FLOAT_MAT2x4 => 2,
+        glow::FLOAT_MAT3 | glow::FLOAT_MAT3x2 | glow::FLOAT_MAT3x4 => 3,
+        glow::FLOAT_MAT4 | glow::FLOAT_MAT4x2 | glow::FLOAT_MAT4x3 => 4,
+        _ => 1,
+    }
+}
+
+/// GLSL spelling of a GL data type, for printing reflection data.
+pub fn type_name(gl_type: u32) -> &'static str {
+    match gl_type {
+        glow::FLOAT => "float",
+        glow::FLOAT_VEC2 => "vec2",
+        glow::FLOAT_VEC3 => "vec3",
+        glow::FLOAT_VEC4 => "vec4",
+        glow::INT => "int",
+        glow::INT_VEC2 => "ivec2",
+        glow::INT_VEC3 => "ivec3",
+        glow::INT_VEC4 => "ivec4",
+        glow::UNSIGNED_INT => "uint",
+        glow::UNSIGNED_INT_VEC2 => "uvec2",
+        glow::UNSIGNED_INT_VEC3 => "uvec3",
+        glow::UNSIGNED_INT_VEC4 => "uvec4",
+        glow::BOOL => "bool",
+        glow::BOOL_VEC2 => "bvec2",
+        glow::BOOL_VEC3 => "bvec3",
+        glow::BOOL_VEC4 => "bvec4",
+        glow::FLOAT_MAT2 => "mat2",
+        glow::FLOAT_MAT3 => "mat3",
+        glow::FLOAT_MAT4 => "mat4",
+        glow::FLOAT_MAT2x3 => "mat2x3",
+        glow::FLOAT_MAT2x4 => "mat2x4",
+        glow::FLOAT_MAT3x2 => "mat3x2",
+        glow::FLOAT_MAT3x4 => "mat3x4",
+        glow::FLOAT_MAT4x2 => "mat4x2",
+        glow::FLOAT_MAT4x3 => "mat4x3",
+        glow::SAMPLER_2D => "sampler2D",
+        glow::SAMPLER_3D => "sampler3D",
+        glow::SAMPLER_CUBE => "samplerCube",
+        glow::SAMPLER_2D_ARRAY => "sampler2DArray",
+        glow::SAMPLER_2D_SHADOW => "sampler2DShadow",
+        glow::INT_SAMPLER_2D => "isampler2D",
+        glow::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        matrix_columns, type_components, AttributeInfo, ProgramReflection, UniformBlockInfo,
+        UniformInfo, VertexInputMismatch,
+    };
+
+    fn attribute(name: &str, gl_type: u32, location: u32) -> AttributeInfo {
+        AttributeInfo {
+            name: name.to_string(),
+            gl_type,
+            size: 1,
+            location: Some(location),
+        }
+    }
+
+    fn uniform(name: &str, gl_type: u32, size: i32, block: Option<usize>) -> UniformInfo {
+        UniformInfo {
+            name: name.to_string(),
+            gl_type,
+            size,
+            location: None,
+            block,
+        }
+    }
+
+    fn reflection() -> ProgramReflection {
+        ProgramReflection {
+            uniforms: vec![
+                uniform("u_model", glow::FLOAT_MAT4, 1, None),
+                uniform("u_lights[0]", glow::FLOAT_VEC3, 4, None),
+                uniform("view_proj", glow::FLOAT_MAT4, 1, Some(0)),
+            ],
+            uniform_blocks: vec![UniformBlockInfo {
+                name: "Camera".to_string(),
+                index: 0,
+                binding: 1,
+                data_size: 64,
+            }],
+            attributes: vec![
+                attribute("position", glow::FLOAT_VEC3, 0),
+                attribute("color", glow::FLOAT_VEC4, 1),
+                attribute("gl_VertexID", glow::INT, 2),
+            ],
+        }
+    }
+
+    #[test]
+    fn counts_components_and_columns_of_gl_types() {
+        assert_eq!(type_components(glow::FLOAT), 1);
+        assert_eq!(type_components(glow::FLOAT_VEC3), 3);
+        assert_eq!(type_components(glow::FLOAT_MAT3x4), 12);
+        assert_eq!(type_components(glow::FLOAT_MAT4), 16);
+        assert_eq!(matrix_columns(glow::FLOAT_MAT4), 4);
+        assert_eq!(matrix_columns(glow::FLOAT_MAT2x3), 2);
+        assert_eq!(matrix_columns(glow::FLOAT_VEC4), 1);
+    }
+
+    #[test]
+    fn finds_arrays_with_or_without_their_index() {
+        let reflection = reflection();
+
+        assert_eq!(reflection.uniform("u_lights").unwrap().size, 4);
+        assert_eq!(reflection.uniform("u_lights[0]").unwrap().size, 4);
+        assert_eq!(reflection.uniform("view_proj").unwrap().block, Some(0));
+        assert!(reflection.uniform("u_missing").is_none());
+        assert_eq!(reflection.uniform_block("Camera").unwrap().data_size, 64);
+        assert_eq!(reflection.attribute("color").unwrap().location, Some(1));
+    }
+
+    #[test]
+    fn reports_missing_and_mismatched_vertex_inputs() {
+        let reflection = reflection();
+
+        assert!(reflection
+            .check_vertex_inputs(&[("position", 3), ("color", 4)])
+            .is_empty());
+        assert_eq!(
+            reflection.check_vertex_inputs(&[("position", 2)]),
+            [
+                VertexInputMismatch::Components {
+                    name: "position".to_string(),
+                    provided: 2,
+                    expected: 3,
+                },
+                VertexInputMismatch::Missing {
+                    name: "color".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn prints_inputs_blocks_and_uniforms() {
+        assert_eq!(
+            reflection().to_string(),
+            "in vec3 position (location 0)\n\
+             in vec4 color (location 1)\n\
+             in int gl_VertexID (location 2)\n\
+             uniform block Camera (binding 1, 64 bytes)\n\
+             uniform mat4 u_model\n\
+             uniform vec3 u_lights[0] [4]\n\
+             uniform mat4 view_proj (in Camera)\n"
+        );
+    }
+}
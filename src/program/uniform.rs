@@ -0,0 +1,297 @@
+use std::fmt;
+
+use gfx_maths::{Mat4, Vec2, Vec3, Vec4};
+use glow::{Context, HasContext, UniformLocation};
+
+use super::reflection::{type_name, UniformInfo};
+
+/// A value for `Program::set_uniform`.
+#[derive(Clone, Copy, Debug)]
+pub enum Uniform {
+    Float(f32),
+    Vec2(Vec2),
+    Vec3(Vec3),
+    Vec4(Vec4),
+    Int(i32),
+    IVec2([i32; 2]),
+    IVec3([i32; 3]),
+    IVec4([i32; 4]),
+    UInt(u32),
+    Bool(bool),
+    Mat4(Mat4),
+    /// Texture unit a sampler reads from.
+    Sampler(u32),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum UniformError {
+    /// The program has no active uniform with this name.
+    Unknown { name: String },
+    /// The value does not match the GLSL type of the uniform.
+    TypeMismatch {
+        name: String,
+        expected: u32,
+        found: &'static str,
+    },
+}
+
+impl fmt::Display for UniformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UniformError::Unknown { name } => write!(f, "no active uniform named {}", name),
+            UniformError::TypeMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "uniform {} is a {}, cannot set it from {}",
+                name,
+                type_name(*expected),
+                found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UniformError {}
+
+fn is_sampler(gl_type: u32) -> bool {
+    matches!(
+        gl_type,
+        glow::SAMPLER_1D
+            | glow::SAMPLER_2D
+            | glow::SAMPLER_3D
+            | glow::SAMPLER_CUBE
+            | glow::SAMPLER_2D_ARRAY
+            | glow::SAMPLER_2D_SHADOW
+            | glow::SAMPLER_2D_ARRAY_SHADOW
+            | glow::SAMPLER_CUBE_SHADOW
+            | glow::INT_SAMPLER_2D
+            | glow::INT_SAMPLER_3D
+            | glow::INT_SAMPLER_CUBE
+            | glow::UNSIGNED_INT_SAMPLER_2D
+            | glow::UNSIGNED_INT_SAMPLER_3D
+            | glow::UNSIGNED_INT_SAMPLER_CUBE
+    )
+}
+
+impl Uniform {
+    /// Rust-side name of the value, for error messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Uniform::Float(_) => "f32",
+            Uniform::Vec2(_) => "Vec2",
+            Uniform::Vec3(_) => "Vec3",
+            Uniform::Vec4(_) => "Vec4",
+            Uniform::Int(_) => "i32",
+            Uniform::IVec2(_) => "[i32; 2]",
+            Uniform::IVec3(_) => "[i32; 3]",
+            Uniform::IVec4(_) => "[i32; 4]",
+            Uniform::UInt(_) => "u32",
+            Uniform::Bool(_) => "bool",
+            Uniform::Mat4(_) => "Mat4",
+            Uniform::Sampler(_) => "Sampler",
+        }
+    }
+
+    /// Whether the value can be assigned to a uniform of GL type `gl_type`.
+    pub fn matches(&self, gl_type: u32) -> bool {
+        match self {
+            Uniform::Float(_) => gl_type == glow::FLOAT,
+            Uniform::Vec2(_) => gl_type == glow::FLOAT_VEC2,
+            Uniform::Vec3(_) => gl_type == glow::FLOAT_VEC3,
+            Uniform::Vec4(_) => gl_type == glow::FLOAT_VEC4,
+            Uniform::Int(_) => gl_type == glow::INT || gl_type == glow::BOOL,
+            Uniform::IVec2(_) => gl_type == glow::INT_VEC2,
+            Uniform::IVec3(_) => gl_type == glow::INT_VEC3,
+            Uniform::IVec4(_) => gl_type == glow::INT_VEC4,
+            Uniform::UInt(_) => gl_type == glow::UNSIGNED_INT,
+            Uniform::Bool(_) => gl_type == glow::BOOL,
+            Uniform::Mat4(_) => gl_type == glow::FLOAT_MAT4,
+            Uniform::Sampler(_) => is_sampler(gl_type),
+        }
+    }
+
+    /// Checks the value against `uniform`, the active uniform `name` refers
+    /// to, if any.
+    pub fn check<'a>(
+        &self,
+        name: &str,
+        uniform: Option<&'a UniformInfo>,
+    ) -> Result<&'a UniformInfo, UniformError> {
+        let uniform = uniform.ok_or_else(|| UniformError::Unknown {
+            name: name.to_string(),
+        })?;
+        if !self.matches(uniform.gl_type) {
+            return Err(UniformError::TypeMismatch {
+                name: name.to_string(),
+                expected: uniform.gl_type,
+                found: self.name(),
+            });
+        }
+        Ok(uniform)
+    }
+
+    /// Uploads the value to `location` of the program in use.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread and `location` must belong to the
+    /// program in use.
+    pub unsafe fn upload(&self, gl: &Context, location: Option<&UniformLocation>) {
+        match *self {
+            Uniform::Float(x) => gl.uniform_1_f32(location, x),
+            Uniform::Vec2(v) => gl.uniform_2_f32(location, v.x, v.y),
+            Uniform::Vec3(v) => gl.uniform_3_f32(location, v.x, v.y, v.z),
+            Uniform::Vec4(v) => gl.uniform_4_f32(location, v.x, v.y, v.z, v.w),
+            Uniform::Int(x) => gl.uniform_1_i32(location, x),
+            Uniform::IVec2([x, y]) => gl.uniform_2_i32(location, x, y),
+            Uniform::IVec3([x, y, z]) => gl.uniform_3_i32(location, x, y, z),
+            Uniform::IVec4([x, y, z, w]) => gl.uniform_4_i32(location, x, y, z, w),
+            Uniform::UInt(x) => gl.uniform_1_u32(location, x),
+            Uniform::Bool(x) => gl.uniform_1_i32(location, x as i32),
+            Uniform::Mat4(matrix) => gl.uniform_matrix_4_f32_slice(location, false, &matrix.values),
+            Uniform::Sampler(unit) => gl.uniform_1_i32(location, unit as i32),
+        }
+    }
+}
+
+impl From<f32> for Uniform {
+    fn from(value: f32) -> Self {
+        Uniform::Float(value)
+    }
+}
+
+impl From<Vec2> for Uniform {
+    fn from(value: Vec2) -> Self {
+        Uniform::Vec2(value)
+    }
+}
+
+impl From<Vec3> for Uniform {
+    fn from(value: Vec3) -> Self {
+        Uniform::Vec3(value)
+    }
+}
+
+impl From<Vec4> for Uniform {
+    fn from(value: Vec4) -> Self {
+        Uniform::Vec4(value)
+    }
+}
+
+impl From<i32> for Uniform {
+    fn from(value: i32) -> Self {
+        Uniform::Int(value)
+    }
+}
+
+impl From<[i32; 2]> for Uniform {
+    fn from(value: [i32; 2]) -> Self {
+        Uniform::IVec2(value)
+    }
+}
+
+impl From<[i32; 3]> for Uniform {
+    fn from(value: [i32; 3]) -> Self {
+        Uniform::IVec3(value)
+    }
+}
+
+impl From<[i32; 4]> for Uniform {
+    fn from(value: [i32; 4]) -> Self {
+        Uniform::IVec4(value)
+    }
+}
+
+impl From<u32> for Uniform {
+    fn from(value: u32) -> Self {
+        Uniform::UInt(value)
+    }
+}
+
+impl From<bool> for Uniform {
+    fn from(value: bool) -> Self {
+        Uniform::Bool(value)
+    }
+}
+
+impl From<Mat4> for Uniform {
+    fn from(value: Mat4) -> Self {
+        Uniform::Mat4(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gfx_maths::{Mat4, Vec3};
+
+    use super::{Uniform, UniformError};
+    use crate::program::reflection::UniformInfo;
+
+    fn uniform(name: &str, gl_type: u32) -> UniformInfo {
+        UniformInfo {
+            name: name.to_string(),
+            gl_type,
+            size: 1,
+            location: None,
+            block: None,
+        }
+    }
+
+    #[test]
+    fn matches_values_to_their_glsl_types() {
+        assert!(Uniform::from(1.0).matches(glow::FLOAT));
+        assert!(!Uniform::from(1.0).matches(glow::INT));
+        assert!(Uniform::from(Vec3::new(0.0, 1.0, 2.0)).matches(glow::FLOAT_VEC3));
+        assert!(!Uniform::from(Vec3::new(0.0, 1.0, 2.0)).matches(glow::FLOAT_VEC4));
+        assert!(Uniform::from(Mat4::identity()).matches(glow::FLOAT_MAT4));
+        assert!(!Uniform::from(Mat4::identity()).matches(glow::FLOAT_MAT3));
+        assert!(Uniform::from(true).matches(glow::BOOL));
+        // GLSL bools can be set from integers, but not the other way round
+        assert!(Uniform::from(1).matches(glow::BOOL));
+        assert!(!Uniform::from(true).matches(glow::INT));
+        assert!(Uniform::from(1u32).matches(glow::UNSIGNED_INT));
+        assert!(Uniform::Sampler(0).matches(glow::SAMPLER_2D));
+        assert!(Uniform::Sampler(0).matches(glow::UNSIGNED_INT_SAMPLER_CUBE));
+        assert!(!Uniform::Sampler(0).matches(glow::INT));
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        assert_eq!(
+            Uniform::from(1.0).check("u_time", None).unwrap_err(),
+            UniformError::Unknown {
+                name: "u_time".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_values_of_another_type() {
+        let model = uniform("u_model", glow::FLOAT_MAT4);
+        let error = Uniform::from(Vec3::new(0.0, 0.0, 0.0))
+            .check("u_model", Some(&model))
+            .unwrap_err();
+
+        assert_eq!(
+            error,
+            UniformError::TypeMismatch {
+                name: "u_model".to_string(),
+                expected: glow::FLOAT_MAT4,
+                found: "Vec3",
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "uniform u_model is a mat4, cannot set it from Vec3"
+        );
+        assert_eq!(
+            Uniform::from(Mat4::identity())
+                .check("u_model", Some(&model))
+                .unwrap()
+                .name,
+            "u_model"
+        );
+    }
+}
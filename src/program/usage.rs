@@ -0,0 +1,245 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use super::reflection::{type_name, ProgramReflection};
+
+/// A difference between the uniforms a program declares and the ones set on
+/// it from Rust during a frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UniformIssue {
+    /// Declared in the source but not active, usually because the compiler
+    /// removed it as unused. `set` tells whether code still sets it.
+    Unused { name: String, set: bool },
+    /// Active, but never set during the frame, so it keeps its default.
+    NeverSet { name: String },
+    /// Set from code, but neither declared nor active.
+    Undeclared { name: String },
+    /// Set from code with a value of the wrong type.
+    TypeMismatch {
+        name: String,
+        expected: u32,
+        found: &'static str,
+    },
+}
+
+impl fmt::Display for UniformIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UniformIssue::Unused { name, set } => {
+                write!(f, "uniform {} is declared but not active", name)?;
+                if *set {
+                    write!(f, ", values set from code are dropped")?;
+                }
+                Ok(())
+            }
+            UniformIssue::NeverSet { name } => {
+                write!(f, "uniform {} is active but never set", name)
+            }
+            UniformIssue::Undeclared { name } => {
+                write!(f, "uniform {} is set from code but not declared", name)
+            }
+            UniformIssue::TypeMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "uniform {} is a {} but is set from {}",
+                name,
+                type_name(*expected),
+                found
+            ),
+        }
+    }
+}
+
+/// What `Program::set_uniform` was asked to do since the program was last
+/// checked.
+#[derive(Debug, Default)]
+pub(super) struct UniformUsage {
+    /// Whether the program was bound, so a check only covers frames it was
+    /// drawn in.
+    pub(super) bound: bool,
+    /// Whether issues were already reported for this program.
+    pub(super) reported: bool,
+    /// Reflection names of the active uniforms that were set.
+    set: HashSet<String>,
+    /// Names that matched no active uniform.
+    unknown: HashSet<String>,
+    mismatched: Vec<UniformIssue>,
+}
+
+impl UniformUsage {
+    pub(super) fn set(&mut self, name: &str) {
+        if !self.set.contains(name) {
+            self.set.insert(name.to_string());
+        }
+    }
+
+    pub(super) fn unknown(&mut self, name: &str) {
+        if !self.unknown.contains(name) {
+            self.unknown.insert(name.to_string());
+        }
+    }
+
+    pub(super) fn mismatched(&mut self, issue: UniformIssue) {
+        if !self.mismatched.contains(&issue) {
+            self.mismatched.push(issue);
+        }
+    }
+
+    /// Compares the recorded calls with the active uniforms of `reflection`
+    /// and the uniforms `declared` in the source.
+    pub(super) fn issues(
+        &self,
+        reflection: &ProgramReflection,
+        declared: &[String],
+    ) -> Vec<UniformIssue> {
+        let active = |name: &str| {
+            reflection.uniforms.iter().any(|uniform| {
+                uniform.block.is_none()
+                    && uniform
+                        .name
+                        .strip_prefix(name)
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+            })
+        };
+
+        let mut issues = vec![];
+        for name in declared.iter().filter(|name| !active(name)) {
+            issues.push(UniformIssue::Unused {
+                name: name.clone(),
+                set: self.unknown.contains(name),
+            });
+        }
+
+        for uniform in reflection.uniforms.iter() {
+            if uniform.block.is_none() && !self.set.contains(&uniform.name) {
+                issues.push(UniformIssue::NeverSet {
+                    name: uniform.name.clone(),
+                });
+            }
+        }
+
+        let mut undeclared: Vec<&String> = self
+            .unknown
+            .iter()
+            .filter(|name| !declared.contains(name))
+            .collect();
+        undeclared.sort();
+        for name in undeclared {
+            issues.push(UniformIssue::Undeclared { name: name.clone() });
+        }
+
+        issues.extend(self.mismatched.iter().cloned());
+        issues
+    }
+
+    /// Forgets the calls of the current frame.
+    pub(super) fn clear(&mut self) {
+        self.bound = false;
+        self.set.clear();
+        self.unknown.clear();
+        self.mismatched.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{UniformIssue, UniformUsage};
+    use crate::program::reflection::{ProgramReflection, UniformInfo};
+
+    fn reflection(uniforms: &[(&str, u32)]) -> ProgramReflection {
+        ProgramReflection {
+            uniforms: uniforms
+                .iter()
+                .map(|(name, gl_type)| UniformInfo {
+                    name: name.to_string(),
+                    gl_type: *gl_type,
+                    size: 1,
+                    location: None,
+                    block: None,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn declared(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn reports_declared_uniforms_the_compiler_removed() {
+        let reflection = reflection(&[("u_model", glow::FLOAT_MAT4)]);
+        let mut usage = UniformUsage::default();
+        usage.set("u_model");
+        usage.unknown("u_tint");
+
+        assert_eq!(
+            usage.issues(&reflection, &declared(&["u_model", "u_tint", "u_fog"])),
+            [
+                UniformIssue::Unused {
+                    name: "u_tint".to_string(),
+                    set: true,
+                },
+                UniformIssue::Unused {
+                    name: "u_fog".to_string(),
+                    set: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_names_neither_declared_nor_active() {
+        let reflection = reflection(&[("u_model", glow::FLOAT_MAT4)]);
+        let mut usage = UniformUsage::default();
+        usage.unknown("u_view");
+        usage.unknown("u_modle");
+
+        assert_eq!(
+            usage.issues(&reflection, &declared(&["u_model"])),
+            [
+                UniformIssue::NeverSet {
+                    name: "u_model".to_string(),
+                },
+                UniformIssue::Undeclared {
+                    name: "u_modle".to_string(),
+                },
+                UniformIssue::Undeclared {
+                    name: "u_view".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_each_type_mismatch_once() {
+        let reflection = reflection(&[("u_model", glow::FLOAT_MAT4)]);
+        let mismatch = UniformIssue::TypeMismatch {
+            name: "u_model".to_string(),
+            expected: glow::FLOAT_MAT4,
+            found: "Vec3",
+        };
+        let mut usage = UniformUsage::default();
+        usage.set("u_model");
+        usage.mismatched(mismatch.clone());
+        usage.mismatched(mismatch.clone());
+
+        let issues = usage.issues(&reflection, &declared(&["u_model"]));
+        assert_eq!(issues, [mismatch]);
+        assert_eq!(
+            issues[0].to_string(),
+            "uniform u_model is a mat4 but is set from Vec3"
+        );
+
+        usage.clear();
+        assert_eq!(
+            usage.issues(&reflection, &declared(&["u_model"])),
+            [UniformIssue::NeverSet {
+                name: "u_model".to_string(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::panic::Location;
+use std::sync::Mutex;
+
+use glow::{Context, HasContext, NativeBuffer, NativeTexture, NativeVertexArray};
+
+use crate::error::Error;
+
+/// GL objects created through the wrappers below and not deleted yet, keyed
+/// by their debug name and pointing at the code that created them. Only
+/// filled in debug builds.
+static LIVE: Mutex<BTreeMap<String, &'static Location<'static>>> = Mutex::new(BTreeMap::new());
+
+/// Records that `native` was created by the caller.
+#[track_caller]
+pub(crate) fn created(native: impl Debug) {
+    if cfg!(debug_assertions) {
+        let mut live = LIVE.lock().unwrap_or_else(|error| error.into_inner());
+        live.insert(format!("{:?}", native), Location::caller());
+    }
+}
+
+/// Records that `native` was deleted.
+pub(crate) fn deleted(native: impl Debug) {
+    if cfg!(debug_assertions) {
+        let mut live = LIVE.lock().unwrap_or_else(|error| error.into_inner());
+        live.remove(&format!("{:?}", native));
+    }
+}
+
+/// GL objects that were created but not deleted yet, with where they were
+/// created. Always empty in release builds.
+pub fn leaks() -> Vec<String> {
+    let live = LIVE.lock().unwrap_or_else(|error| error.into_inner());
+    live.iter()
+        .map(|(name, location)| format!("{} created at {}", name, location))
+        .collect()
+}
+
+/// Prints every leaked GL object, returning how many there were. Meant to
+/// be called at shutdown, once everything was detached.
+pub fn report_leaks() -> usize {
+    let leaks = leaks();
+    for leak in leaks.iter() {
+        eprintln!("leaked {}", leak);
+    }
+    leaks.len()
+}
+
+/// How often the contents of a buffer change, which lets the driver pick
+/// where to store it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferUsage {
+    /// Written once and drawn many times.
+    Static,
+    /// Written now and then, e.g. when an object is resized.
+    Dynamic,
+    /// Written every frame.
+    Stream,
+}
+
+impl BufferUsage {
+    /// The `glow::*_DRAW` constant passed to `glBufferData`.
+    pub fn gl_usage(self) -> u32 {
+        match self {
+            BufferUsage::Static => glow::STATIC_DRAW,
+            BufferUsage::Dynamic => glow::DYNAMIC_DRAW,
+            BufferUsage::Stream => glow::STREAM_DRAW,
+        }
+    }
+}
+
+/// How a buffer with `capacity` bytes of storage grows to fit `size` bytes:
+/// the new capacity, at least double the old one, and how many bytes of the
+/// old contents to copy over. `None` when `size` already fits.
+fn growth(capacity: usize, size: usize) -> Option<(usize, usize)> {
+    if size <= capacity {
+        return None;
+    }
+    Some((size.max(capacity * 2), capacity))
+}
+
+/// A buffer object bound to one target, e.g. `glow::ARRAY_BUFFER`.
+///
+/// GL objects can only be deleted while their context is current, so they
+/// are not freed on drop; call `delete` from `detach`.
+#[derive(Debug)]
+pub struct Buffer {
+    native: NativeBuffer,
+    target: u32,
+    usage: BufferUsage,
+    /// Bytes of storage allocated for the buffer.
+    capacity: usize,
+}
+
+impl Buffer {
+    /// Creates a buffer without storage; `data` or `reserve` allocate it.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    #[track_caller]
+    pub unsafe fn new(gl: &Context, target: u32, usage: BufferUsage) -> Result<Self, Error> {
+        let native = gl.create_buffer().map_err(Error::Gl)?;
+        created(native);
+        Ok(Self {
+            native,
+            target,
+            usage,
+            capacity: 0,
+        })
+    }
+
+    pub fn native(&self) -> NativeBuffer {
+        self.native
+    }
+
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+
+    pub fn usage(&self) -> BufferUsage {
+        self.usage
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn bind(&self, gl: &Context) {
+        gl.bind_buffer(self.target, Some(self.native));
+    }
+
+    /// Binds the buffer and replaces its storage with `data`.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn data(&mut self, gl: &Context, data: &[u8]) {
+        self.bind(gl);
+        gl.buffer_data_u8_slice(self.target, data, self.usage.gl_usage());
+        self.capacity = data.len();
+    }
+
+    /// Binds the buffer and writes `data` at `offset` bytes, keeping the
+    /// rest of the contents. The buffer grows when `data` ends past its
+    /// capacity.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn sub_data(
+        &mut self,
+        gl: &Context,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        self.reserve(gl, offset + data.len())?;
+        self.bind(gl);
+        gl.buffer_sub_data_u8_slice(self.target, offset as i32, data);
+        Ok(())
+    }
+
+    /// Grows the storage to at least `size` bytes, at least doubling it so
+    /// repeated growth stays cheap. The contents are kept, and so is the
+    /// buffer name, so vertex arrays pointing at it stay valid.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn reserve(&mut self, gl: &Context, size: usize) -> Result<(), Error> {
+        let (capacity, kept) = match growth(self.capacity, size) {
+            Some(growth) => growth,
+            None => return Ok(()),
+        };
+
+        // The copy targets leave the bindings of the current vertex array alone
+        if kept == 0 {
+            gl.bind_buffer(glow::COPY_WRITE_BUFFER, Some(self.native));
+            gl.buffer_data_size(
+                glow::COPY_WRITE_BUFFER,
+                capacity as i32,
+                self.usage.gl_usage(),
+            );
+        } else {
+            let old = kept as i32;
+            let copy = gl.create_buffer().map_err(Error::Gl)?;
+
+            gl.bind_buffer(glow::COPY_READ_BUFFER, Some(self.native));
+            gl.bind_buffer(glow::COPY_WRITE_BUFFER, Some(copy));
+            gl.buffer_data_size(glow::COPY_WRITE_BUFFER, old, glow::STREAM_COPY);
+            gl.copy_buffer_sub_data(glow::COPY_READ_BUFFER, glow::COPY_WRITE_BUFFER, 0, 0, old);
+
+            gl.bind_buffer(glow::COPY_READ_BUFFER, Some(copy));
+            gl.bind_buffer(glow::COPY_WRITE_BUFFER, Some(self.native));
+            gl.buffer_data_size(
+                glow::COPY_WRITE_BUFFER,
+                capacity as i32,
+                self.usage.gl_usage(),
+            );
+            gl.copy_buffer_sub_data(glow::COPY_READ_BUFFER, glow::COPY_WRITE_BUFFER, 0, 0, old);
+
+            gl.delete_buffer(copy);
+        }
+        self.capacity = capacity;
+        Ok(())
+    }
+
+    /// Gives the buffer new storage of the same size and undefined contents,
+    /// so the next write does not wait for draws still reading the old
+    /// contents. Meant for `BufferUsage::Stream` buffers rewritten every
+    /// frame.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn orphan(&self, gl: &Context) {
+        self.bind(gl);
+        gl.buffer_data_size(self.target, self.capacity as i32, self.usage.gl_usage());
+    }
+
+    /// # Safety
+    /// `gl` must be current on this thread and own the buffer.
+    pub unsafe fn delete(self, gl: &Context) {
+        gl.delete_buffer(self.native);
+        deleted(self.native);
+    }
+}
+
+/// A vertex array object, see `Buffer` for how it is released.
+#[derive(Debug)]
+pub struct VertexArray {
+    native: NativeVertexArray,
+}
+
+impl VertexArray {
+    /// # Safety
+    /// `gl` must be current on this thread.
+    #[track_caller]
+    pub unsafe fn new(gl: &Context) -> Result<Self, Error> {
+        let native = gl.create_vertex_array().map_err(Error::Gl)?;
+        created(native);
+        Ok(Self { native })
+    }
+
+    pub fn native(&self) -> NativeVertexArray {
+        self.native
+    }
+
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn bind(&self, gl: &Context) {
+        gl.bind_vertex_array(Some(self.native));
+    }
+
+    /// # Safety
+    /// `gl` must be current on this thread and own the vertex array.
+    pub unsafe fn delete(self, gl: &Context) {
+        gl.delete_vertex_array(self.native);
+        deleted(self.native);
+    }
+}
+
+/// A texture bound to one target, e.g. `glow::TEXTURE_2D`, see `Buffer`
+/// for how it is released.
+#[derive(Debug)]
+pub struct Texture {
+    native: NativeTexture,
+    target: u32,
+}
+
+impl Texture {
+    /// # Safety
+    /// `gl` must be current on this thread.
+    #[track_caller]
+    pub unsafe fn new(gl: &Context, target: u32) -> Result<Self, Error> {
+        let native = gl.create_texture().map_err(Error::Gl)?;
+        created(native);
+        Ok(Self { native, target })
+    }
+
+    pub fn native(&self) -> NativeTexture {
+        self.native
+    }
+
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+
+    /// Binds the texture to texture unit `unit`.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn bind(&self, gl: &Context, unit: u32) {
+        gl.active_texture(glow::TEXTURE0 + unit);
+        gl.bind_texture(self.target, Some(self.native));
+    }
+
+    /// # Safety
+    /// `gl` must be current on this thread and own the texture.
+    pub unsafe fn delete(self, gl: &Context) {
+        gl.delete_texture(self.native);
+        deleted(self.native);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::growth;
+
+    #[test]
+    fn keeps_storage_that_is_large_enough() {
+        assert_eq!(growth(64, 0), None);
+        assert_eq!(growth(64, 64), None);
+    }
+
+    #[test]
+    fn allocates_exactly_the_first_size() {
+        assert_eq!(growth(0, 48), Some((48, 0)));
+    }
+
+    #[test]
+    fn doubles_the_capacity_and_keeps_the_contents() {
+        assert_eq!(growth(64, 65), Some((128, 64)));
+        assert_eq!(growth(64, 100), Some((128, 64)));
+        // Writes past double the capacity get exactly what they need
+        assert_eq!(growth(64, 300), Some((300, 64)));
+    }
+}
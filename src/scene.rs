@@ -0,0 +1,629 @@
+use std::any::Any;
+use std::fmt;
+
+use gfx_maths::Mat4;
+use glow::Context;
+
+use crate::error::Error;
+use crate::object::{OpenGLObjectTrait, TestingEvent};
+use crate::program::cache::ProgramCache;
+use crate::transform::Transform;
+
+/// A handle to a node of a `Scene`. Handles of removed nodes stay invalid
+/// even when their slot is reused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: usize,
+    generation: u32,
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+/// A scene operation that names a node it cannot use.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SceneError {
+    /// The node was removed from the scene.
+    Removed { node: NodeId },
+    /// The new parent is the node itself or one of its descendants.
+    Cycle { node: NodeId, parent: NodeId },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Removed { node } => write!(f, "node {} was removed", node),
+            SceneError::Cycle { node, parent } => write!(
+                f,
+                "node {} cannot become a child of node {}, its descendant",
+                node, parent
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+/// Where the object of a node is in its lifecycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ObjectState {
+    /// Not attached yet, it is on its first frame.
+    Detached,
+    Attached,
+    /// Attaching or updating failed, the object was detached and is skipped
+    /// until its shader changes, see `Scene::retry_failed`.
+    Failed,
+}
+
+struct Node<'a> {
+    transform: Transform,
+    /// `transform` combined with the transforms of every ancestor.
+    world: Mat4,
+    /// Set when `transform` or the parent changed since `world` was computed.
+    dirty: bool,
+    visible: bool,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    object: Option<Box<dyn OpenGLObjectTrait + 'a>>,
+    state: ObjectState,
+}
+
+/// One entry of `Scene::slots`, reused once its node is removed.
+struct Slot<'a> {
+    /// Bumped every time the node is removed, to invalidate its handles.
+    generation: u32,
+    node: Option<Node<'a>>,
+}
+
+/// A tree of nodes drawn by `WindowTrait::render_scene`, so objects can be
+/// grouped and moved together.
+///
+/// Every node has a transform relative to its parent. Nodes can hold an
+/// object, which is drawn with the world matrix of its node applied on top
+/// of its own transform, see `OpenGLObjectTrait::set_parent_matrix`.
+/// Parents are drawn before their children, and siblings in the order they
+/// were added.
+///
+/// The scene owns its objects. Added objects are attached on the next frame
+/// and removed ones detached on the next frame, so nodes can be added and
+/// removed between frames, e.g. from the callback of
+/// `WindowTrait::render_scene_with`.
+#[derive(Default)]
+pub struct Scene<'a> {
+    slots: Vec<Slot<'a>>,
+    /// Indices of the slots without a node.
+    free: Vec<usize>,
+    roots: Vec<NodeId>,
+    /// Objects removed while attached, detached on the next frame.
+    removed: Vec<Box<dyn OpenGLObjectTrait + 'a>>,
+}
+
+impl<'a> Scene<'a> {
+    pub fn new() -> Self {
+        Self {
+            slots: vec![],
+            free: vec![],
+            roots: vec![],
+            removed: vec![],
+        }
+    }
+
+    /// Adds an empty node, e.g. to group other nodes under one transform.
+    pub fn add_node(&mut self, parent: Option<NodeId>) -> Result<NodeId, SceneError> {
+        self.push(parent, None)
+    }
+
+    /// Adds a node holding `object`, attached on the next frame. Objects
+    /// can also be borrowed, as `&mut` references are objects too.
+    pub fn add_object(
+        &mut self,
+        parent: Option<NodeId>,
+        object: impl OpenGLObjectTrait + 'a,
+    ) -> Result<NodeId, SceneError> {
+        self.push(parent, Some(Box::new(object)))
+    }
+
+    fn push(
+        &mut self,
+        parent: Option<NodeId>,
+        object: Option<Box<dyn OpenGLObjectTrait + 'a>>,
+    ) -> Result<NodeId, SceneError> {
+        if let Some(parent) = parent {
+            self.node(parent)?;
+        }
+
+        let node = Node {
+            transform: Transform::new(),
+            world: Mat4::identity(),
+            dirty: true,
+            visible: true,
+            parent,
+            children: vec![],
+            object,
+            state: ObjectState::Detached,
+        };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.slots[index].node = Some(node);
+                index
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    node: Some(node),
+                });
+                self.slots.len() - 1
+            }
+        };
+        let id = NodeId {
+            index,
+            generation: self.slots[index].generation,
+        };
+        self.siblings_mut(parent).push(id);
+        Ok(id)
+    }
+
+    /// Removes `node` and its descendants. Their objects are detached on
+    /// the next frame, then dropped.
+    pub fn remove(&mut self, node: NodeId) -> Result<(), SceneError> {
+        let parent = self.node(node)?.parent;
+        self.siblings_mut(parent).retain(|sibling| *sibling != node);
+
+        let mut stack = vec![node];
+        while let Some(id) = stack.pop() {
+            let slot = &mut self.slots[id.index];
+            let removed = match slot.node.take() {
+                Some(removed) => removed,
+                None => continue,
+            };
+            slot.generation = slot.generation.wrapping_add(1);
+            self.free.push(id.index);
+
+            stack.extend(removed.children);
+            if let Some(object) = removed.object {
+                if removed.state == ObjectState::Attached {
+                    self.removed.push(object);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, node: NodeId) -> bool {
+        self.node(node).is_ok()
+    }
+
+    fn node(&self, id: NodeId) -> Result<&Node<'a>, SceneError> {
+        self.slots
+            .get(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+            .ok_or(SceneError::Removed { node: id })
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Result<&mut Node<'a>, SceneError> {
+        self.slots
+            .get_mut(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+            .ok_or(SceneError::Removed { node: id })
+    }
+
+    /// The children of `parent`, or the roots. `parent` must be valid.
+    fn siblings_mut(&mut self, parent: Option<NodeId>) -> &mut Vec<NodeId> {
+        match parent {
+            Some(parent) => {
+                &mut self.slots[parent.index]
+                    .node
+                    .as_mut()
+                    .expect("parent was checked")
+                    .children
+            }
+            None => &mut self.roots,
+        }
+    }
+
+    /// The object held by `node`, if it holds one and was not removed.
+    pub fn get(&self, node: NodeId) -> Option<&(dyn OpenGLObjectTrait + 'a)> {
+        self.node(node).ok()?.object.as_deref()
+    }
+
+    /// The object held by `node`, if it holds one and was not removed.
+    pub fn get_mut(&mut self, node: NodeId) -> Option<&mut (dyn OpenGLObjectTrait + 'a)> {
+        self.node_mut(node).ok()?.object.as_deref_mut()
+    }
+
+    /// The object held by `node` as a `T`, if it is one, e.g. to change the
+    /// fields of a `Rectangle`. See `OpenGLObjectTrait::as_any`.
+    pub fn get_as<T: Any>(&self, node: NodeId) -> Option<&T> {
+        self.get(node)?.as_any()?.downcast_ref()
+    }
+
+    pub fn get_mut_as<T: Any>(&mut self, node: NodeId) -> Option<&mut T> {
+        self.get_mut(node)?.as_any_mut()?.downcast_mut()
+    }
+
+    pub fn transform(&self, node: NodeId) -> Option<&Transform> {
+        Some(&self.node(node).ok()?.transform)
+    }
+
+    /// The transform of `node` relative to its parent. The world matrices
+    /// of the node and its descendants are recomputed on the next frame.
+    pub fn transform_mut(&mut self, node: NodeId) -> Option<&mut Transform> {
+        let node = self.node_mut(node).ok()?;
+        node.dirty = true;
+        Some(&mut node.transform)
+    }
+
+    /// The transform of `node` combined with those of its ancestors, as of
+    /// the last frame.
+    pub fn world_matrix(&self, node: NodeId) -> Option<Mat4> {
+        Some(self.node(node).ok()?.world)
+    }
+
+    pub fn is_visible(&self, node: NodeId) -> Option<bool> {
+        Some(self.node(node).ok()?.visible)
+    }
+
+    /// Hides or shows `node` and its descendants. Hidden objects stay
+    /// attached and updated, they are only not drawn.
+    pub fn set_visible(&mut self, node: NodeId, visible: bool) -> Result<(), SceneError> {
+        self.node_mut(node)?.visible = visible;
+        Ok(())
+    }
+
+    pub fn parent(&self, node: NodeId) -> Option<NodeId> {
+        self.node(node).ok()?.parent
+    }
+
+    pub fn children(&self, node: NodeId) -> &[NodeId] {
+        self.node(node)
+            .map(|node| node.children.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The nodes without a parent.
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    /// Moves `node`, with its descendants, under `parent`, or to the roots.
+    /// Its transform is kept, so it moves along with its new parent.
+    pub fn set_parent(&mut self, node: NodeId, parent: Option<NodeId>) -> Result<(), SceneError> {
+        let old_parent = self.node(node)?.parent;
+        if let Some(parent) = parent {
+            let mut ancestor = Some(parent);
+            while let Some(current) = ancestor {
+                if current == node {
+                    return Err(SceneError::Cycle { node, parent });
+                }
+                ancestor = self.node(current)?.parent;
+            }
+        }
+
+        self.siblings_mut(old_parent)
+            .retain(|sibling| *sibling != node);
+        self.siblings_mut(parent).push(node);
+
+        let moved = self.node_mut(node)?;
+        moved.parent = parent;
+        moved.dirty = true;
+        Ok(())
+    }
+
+    /// Every node, parents before their children.
+    fn order(&self) -> Vec<NodeId> {
+        let mut order = Vec::with_capacity(self.slots.len() - self.free.len());
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(
+                self.slots[id.index]
+                    .node
+                    .iter()
+                    .flat_map(|node| node.children.iter().rev()),
+            );
+        }
+        order
+    }
+
+    /// Detaches the removed objects, attaches the new ones, then forwards a
+    /// resize and updates them. An object whose attach or update fails is
+    /// reported and detached, and the others keep running.
+    pub(crate) fn step(
+        &mut self,
+        gl: &Context,
+        programs: &mut ProgramCache,
+        resize: Option<&TestingEvent>,
+    ) {
+        for mut object in self.removed.drain(..) {
+            object.detach(gl, programs);
+        }
+
+        for id in self.order() {
+            let node = match self.slots[id.index].node.as_mut() {
+                Some(node) => node,
+                None => continue,
+            };
+            let object = match node.object.as_deref_mut() {
+                Some(object) if node.state != ObjectState::Failed => object,
+                _ => continue,
+            };
+            if let Err(error) = step_object(gl, object, &mut node.state, programs, resize) {
+                eprintln!("{}", error);
+                object.detach(gl, programs);
+                node.state = ObjectState::Failed;
+            }
+        }
+        self.update_world();
+    }
+
+    /// The shader files of the objects that failed and their defines, to
+    /// watch for a fix.
+    pub(crate) fn failed_sources(&self) -> Vec<(&str, &[(String, String)])> {
+        self.slots
+            .iter()
+            .filter_map(|slot| slot.node.as_ref())
+            .filter(|node| node.state == ObjectState::Failed)
+            .filter_map(|node| {
+                let object = node.object.as_ref()?;
+                let path = object.shader_source()?.file_path()?.to_str()?;
+                Some((path, object.shader_defines()))
+            })
+            .collect()
+    }
+
+    /// Attaches the failed objects drawn with the shader file `path` again
+    /// on the next step, after it changed on disk.
+    pub(crate) fn retry_failed(&mut self, path: &str) {
+        for node in self.slots.iter_mut().filter_map(|slot| slot.node.as_mut()) {
+            let uses_path = node
+                .object
+                .as_ref()
+                .and_then(|object| object.shader_source())
+                .is_some_and(|source| source.name() == path && source.file_path().is_some());
+            if node.state == ObjectState::Failed && uses_path {
+                node.state = ObjectState::Detached;
+            }
+        }
+    }
+
+    /// Recomputes the world matrices of the nodes that moved, and of their
+    /// descendants, and hands them to their objects.
+    fn update_world(&mut self) {
+        let mut stack: Vec<(NodeId, Mat4, bool)> = self
+            .roots
+            .iter()
+            .rev()
+            .map(|root| (*root, Mat4::identity(), false))
+            .collect();
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = match self.slots[id.index].node.as_mut() {
+                Some(node) => node,
+                None => continue,
+            };
+            let changed = node.dirty || parent_changed;
+            if changed {
+                node.world = parent_world * node.transform.matrix();
+                node.dirty = false;
+                if let Some(object) = node.object.as_deref_mut() {
+                    object.set_parent_matrix(node.world);
+                }
+            }
+            for child in node.children.iter().rev() {
+                stack.push((*child, node.world, changed));
+            }
+        }
+    }
+
+    /// Renders the attached objects of the visible nodes.
+    pub(crate) fn render(&mut self, gl: &Context) {
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            let node = match self.slots[id.index].node.as_mut() {
+                Some(node) if node.visible => node,
+                _ => continue,
+            };
+            if let Some(object) = node.object.as_deref_mut() {
+                if node.state == ObjectState::Attached {
+                    object.render(gl);
+                }
+            }
+            stack.extend(node.children.iter().rev());
+        }
+    }
+
+    /// Detaches every attached object, on exit. They are attached again if
+    /// the scene is rendered again.
+    pub(crate) fn detach(&mut self, gl: &Context, programs: &mut ProgramCache) {
+        for mut object in self.removed.drain(..) {
+            object.detach(gl, programs);
+        }
+        for node in self.slots.iter_mut().filter_map(|slot| slot.node.as_mut()) {
+            if let Some(object) = node.object.as_deref_mut() {
+                if node.state == ObjectState::Attached {
+                    object.detach(gl, programs);
+                    node.state = ObjectState::Detached;
+                }
+            }
+        }
+    }
+}
+
+/// Attaches `object` on its first frame, then forwards a resize and updates
+/// it.
+fn step_object(
+    gl: &Context,
+    object: &mut dyn OpenGLObjectTrait,
+    state: &mut ObjectState,
+    programs: &mut ProgramCache,
+    resize: Option<&TestingEvent>,
+) -> Result<(), Error> {
+    if *state == ObjectState::Detached {
+        // Set first so a failed attach is detached too, releasing what it got
+        *state = ObjectState::Attached;
+        object.attach(gl, programs)?;
+    }
+    if let Some(sizes) = resize {
+        object.window_resize(
+            [sizes.window_draw_resize[0], sizes.window_draw_resize[1]],
+            [sizes.window_resize[0], sizes.window_resize[1]],
+        )
+    }
+    object.update(gl, programs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use gfx_maths::{Mat4, Vec3};
+    use glow::Context;
+
+    use super::{Scene, SceneError};
+    use crate::error::Error;
+    use crate::object::OpenGLObjectTrait;
+    use crate::primitives::rectangle::Rectangle;
+    use crate::primitives::triangle::Triangle;
+    use crate::program::cache::ProgramCache;
+
+    /// Records the world matrix handed down by the scene.
+    struct Probe(Rc<Cell<Option<Mat4>>>);
+
+    impl OpenGLObjectTrait for Probe {
+        fn attach(&mut self, _gl: &Context, _programs: &mut ProgramCache) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn render(&mut self, _gl: &Context) {}
+
+        fn detach(&mut self, _gl: &Context, _programs: &mut ProgramCache) {}
+
+        fn set_parent_matrix(&mut self, matrix: Mat4) {
+            self.0.set(Some(matrix));
+        }
+
+        fn window_resize(&mut self, _draw_size: [f32; 2], _size: [f32; 2]) {}
+    }
+
+    #[test]
+    fn invalidates_handles_of_removed_nodes() {
+        let mut scene = Scene::new();
+        let removed = scene.add_node(None).unwrap();
+        scene.remove(removed).unwrap();
+        let reused = scene.add_node(None).unwrap();
+
+        assert_ne!(removed, reused);
+        assert!(!scene.contains(removed));
+        assert!(scene.contains(reused));
+        assert!(scene.transform_mut(removed).is_none());
+        assert_eq!(
+            scene.remove(removed),
+            Err(SceneError::Removed { node: removed })
+        );
+        assert_eq!(
+            scene.add_node(Some(removed)),
+            Err(SceneError::Removed { node: removed })
+        );
+        assert_eq!(scene.roots(), [reused]);
+    }
+
+    #[test]
+    fn removes_descendants_with_their_parent() {
+        let mut scene = Scene::new();
+        let parent = scene.add_node(None).unwrap();
+        let child = scene.add_node(Some(parent)).unwrap();
+        let grandchild = scene.add_node(Some(child)).unwrap();
+
+        scene.remove(child).unwrap();
+        assert!(scene.contains(parent));
+        assert!(!scene.contains(grandchild));
+        assert!(scene.children(parent).is_empty());
+    }
+
+    #[test]
+    fn rejects_parenting_under_a_descendant() {
+        let mut scene = Scene::new();
+        let node = scene.add_node(None).unwrap();
+        let child = scene.add_node(Some(node)).unwrap();
+        let grandchild = scene.add_node(Some(child)).unwrap();
+
+        assert_eq!(
+            scene.set_parent(node, Some(grandchild)),
+            Err(SceneError::Cycle {
+                node,
+                parent: grandchild
+            })
+        );
+        assert_eq!(
+            scene.set_parent(node, Some(node)),
+            Err(SceneError::Cycle { node, parent: node })
+        );
+        assert_eq!(scene.parent(child), Some(node));
+        assert_eq!(scene.roots(), [node]);
+
+        scene.set_parent(grandchild, None).unwrap();
+        assert_eq!(scene.roots(), [node, grandchild]);
+        assert!(scene.children(child).is_empty());
+    }
+
+    #[test]
+    fn propagates_world_matrices_to_descendants() {
+        let mut scene = Scene::new();
+        let parent = scene.add_node(None).unwrap();
+        let world = Rc::new(Cell::new(None));
+        let child = scene
+            .add_object(Some(parent), Probe(world.clone()))
+            .unwrap();
+        scene.transform_mut(parent).unwrap().translation = Vec3::new(10.0, 0.0, 0.0);
+        scene.transform_mut(child).unwrap().translation = Vec3::new(0.0, 5.0, 0.0);
+
+        scene.update_world();
+        let expected = Mat4::translate(Vec3::new(10.0, 5.0, 0.0));
+        assert_eq!(scene.world_matrix(child), Some(expected));
+        assert_eq!(world.take(), Some(expected));
+
+        // Unchanged nodes are not handed their matrix again
+        scene.update_world();
+        assert_eq!(world.take(), None);
+
+        scene.transform_mut(parent).unwrap().translation = Vec3::new(0.0, 0.0, 0.0);
+        scene.update_world();
+        let expected = Mat4::translate(Vec3::new(0.0, 5.0, 0.0));
+        assert_eq!(scene.world_matrix(child), Some(expected));
+        assert_eq!(world.take(), Some(expected));
+
+        let other = scene.add_node(None).unwrap();
+        scene.transform_mut(other).unwrap().translation = Vec3::new(0.0, 0.0, 3.0);
+        scene.set_parent(child, Some(other)).unwrap();
+        scene.update_world();
+        assert_eq!(
+            scene.world_matrix(child),
+            Some(Mat4::translate(Vec3::new(0.0, 5.0, 3.0)))
+        );
+    }
+
+    #[test]
+    fn hands_out_objects_by_type() {
+        let mut borrowed = Rectangle::new(10, 10, "missing.shader");
+        let mut scene = Scene::new();
+        let owned = scene
+            .add_object(None, Rectangle::new(20, 20, "missing.shader"))
+            .unwrap();
+        let reference = scene.add_object(None, &mut borrowed).unwrap();
+        let probe = scene
+            .add_object(None, Probe(Rc::new(Cell::new(None))))
+            .unwrap();
+
+        scene.get_mut_as::<Rectangle>(owned).unwrap().width = 40;
+        assert_eq!(scene.get_as::<Rectangle>(owned).unwrap().width, 40);
+        assert_eq!(scene.get_as::<Rectangle>(reference).unwrap().width, 10);
+        assert!(scene.get_as::<Triangle>(owned).is_none());
+        // Objects without `as_any` are never handed out
+        assert!(scene.get_as::<Probe>(probe).is_none());
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+/// Follows `#define`, `#undef` and the `#if` family of directives while a
+/// stage is split, so `#include`s inside sections the preprocessor will drop
+/// are not pasted.
+///
+/// Every answer is three-valued: `None` means the outcome depends on
+/// something only the driver knows, such as `GL_ES` or an `#if` expression
+/// this type does not evaluate. Unknown sections are treated as active.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Conditionals {
+    /// Names defined (`Some(true)`), undefined (`Some(false)`) or defined in
+    /// a section of unknown state (`None`).
+    defined: HashMap<String, Option<bool>>,
+    stack: Vec<Branch>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Branch {
+    /// Whether the current section of this `#if` group is compiled.
+    current: Option<bool>,
+    /// Whether an earlier section of the group was compiled.
+    taken: Option<bool>,
+}
+
+fn not(value: Option<bool>) -> Option<bool> {
+    value.map(|value| !value)
+}
+
+fn and(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    match (a, b) {
+        (Some(false), _) | (_, Some(false)) => Some(false),
+        (Some(true), Some(true)) => Some(true),
+        _ => None,
+    }
+}
+
+fn or(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    not(and(not(a), not(b)))
+}
+
+impl Conditionals {
+    /// Starts with the names injected through `ShaderDataBuilder::define`.
+    pub(super) fn new<'a>(defines: impl Iterator<Item = &'a str>) -> Self {
+        Self {
+            defined: defines.map(|name| (name.to_string(), Some(true))).collect(),
+            stack: vec![],
+        }
+    }
+
+    /// Whether lines at the current position reach the compiler.
+    pub(super) fn active(&self) -> Option<bool> {
+        let mut active = Some(true);
+        for branch in self.stack.iter() {
+            active = and(active, branch.current);
+        }
+        active
+    }
+
+    /// Updates the state for a line of source. Lines that are not
+    /// preprocessor directives are ignored.
+    pub(super) fn track(&mut self, line: &str) {
+        let directive = match line.trim_start().strip_prefix('#') {
+            Some(directive) => directive.trim_start(),
+            None => return,
+        };
+        let (keyword, rest) = directive
+            .split_once(char::is_whitespace)
+            .unwrap_or((directive, ""));
+        let rest = rest.trim();
+
+        let name = rest
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .next()
+            .unwrap_or("");
+
+        match keyword {
+            "ifdef" => self.push(self.is_defined(name)),
+            "ifndef" => self.push(not(self.is_defined(name))),
+            "if" => self.push(self.evaluate(rest)),
+            "elif" => {
+                let condition = self.evaluate(rest);
+                if let Some(branch) = self.stack.last_mut() {
+                    branch.current = and(not(branch.taken), condition);
+                    branch.taken = or(branch.taken, condition);
+                }
+            }
+            "else" => {
+                if let Some(branch) = self.stack.last_mut() {
+                    branch.current = not(branch.taken);
+                    branch.taken = Some(true);
+                }
+            }
+            "endif" => {
+                self.stack.pop();
+            }
+            "define" | "undef" => {
+                let value = match self.active() {
+                    Some(false) => return,
+                    Some(true) => Some(keyword == "define"),
+                    None => None,
+                };
+                self.defined.insert(name.to_string(), value);
+            }
+            _ => {}
+        }
+    }
+
+    fn push(&mut self, condition: Option<bool>) {
+        self.stack.push(Branch {
+            current: condition,
+            taken: condition,
+        });
+    }
+
+    /// Names the driver defines itself (`GL_ES`, `__VERSION__`, extension
+    /// macros) are unknown, every other name is undefined until defined.
+    fn is_defined(&self, name: &str) -> Option<bool> {
+        match self.defined.get(name) {
+            Some(defined) => *defined,
+            None if name.starts_with("GL_") || name.starts_with("__") => None,
+            None => Some(false),
+        }
+    }
+
+    /// Evaluates `0`, `1`, `defined NAME`, `defined(NAME)` and their
+    /// negations; anything else is unknown.
+    fn evaluate(&self, expression: &str) -> Option<bool> {
+        let expression = expression.trim();
+        if let Some(inner) = expression.strip_prefix('!') {
+            return not(self.evaluate(inner));
+        }
+        if let Some(name) = expression.strip_prefix("defined") {
+            let name = name.trim();
+            let name = name
+                .strip_prefix('(')
+                .and_then(|name| name.strip_suffix(')'))
+                .unwrap_or(name)
+                .trim();
+            let identifier =
+                !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+            return if identifier {
+                self.is_defined(name)
+            } else {
+                None
+            };
+        }
+        match expression {
+            "0" => Some(false),
+            "1" => Some(true),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Conditionals;
+
+    fn track(conditionals: &mut Conditionals, source: &str) {
+        for line in source.lines() {
+            conditionals.track(line);
+        }
+    }
+
+    #[test]
+    fn follows_ifdef_and_ifndef() {
+        let mut conditionals = Conditionals::new(["SHIFTED"].into_iter());
+        assert_eq!(conditionals.active(), Some(true));
+
+        track(&mut conditionals, "#ifdef SHIFTED");
+        assert_eq!(conditionals.active(), Some(true));
+        track(&mut conditionals, "#else");
+        assert_eq!(conditionals.active(), Some(false));
+        track(&mut conditionals, "#endif\n#ifndef SHIFTED");
+        assert_eq!(conditionals.active(), Some(false));
+        track(&mut conditionals, "#endif");
+        assert_eq!(conditionals.active(), Some(true));
+    }
+
+    #[test]
+    fn nests_sections() {
+        let mut conditionals = Conditionals::new(["A"].into_iter());
+
+        track(&mut conditionals, "#ifdef A\n#ifdef B");
+        assert_eq!(conditionals.active(), Some(false));
+        track(&mut conditionals, "#else");
+        assert_eq!(conditionals.active(), Some(true));
+        track(&mut conditionals, "#endif");
+        assert_eq!(conditionals.active(), Some(true));
+        track(&mut conditionals, "#else\n#ifdef A");
+        // Inside a disabled outer section, whatever the inner condition
+        assert_eq!(conditionals.active(), Some(false));
+        track(&mut conditionals, "#endif\n#endif");
+        assert_eq!(conditionals.active(), Some(true));
+    }
+
+    #[test]
+    fn takes_first_matching_elif() {
+        let mut conditionals = Conditionals::new(["B"].into_iter());
+
+        track(&mut conditionals, "#if defined(A)");
+        assert_eq!(conditionals.active(), Some(false));
+        track(&mut conditionals, "#elif defined B");
+        assert_eq!(conditionals.active(), Some(true));
+        track(&mut conditionals, "#elif 1");
+        assert_eq!(conditionals.active(), Some(false));
+        track(&mut conditionals, "#else");
+        assert_eq!(conditionals.active(), Some(false));
+        track(&mut conditionals, "#endif\n#if !0");
+        assert_eq!(conditionals.active(), Some(true));
+    }
+
+    #[test]
+    fn defines_only_in_active_sections() {
+        let mut conditionals = Conditionals::new(std::iter::empty());
+
+        track(&mut conditionals, "#if 0\n#define A\n#endif\n#ifdef A");
+        assert_eq!(conditionals.active(), Some(false));
+        track(&mut conditionals, "#endif\n#define B 2\n#ifdef B");
+        assert_eq!(conditionals.active(), Some(true));
+        track(&mut conditionals, "#endif\n#undef B\n#ifdef B");
+        assert_eq!(conditionals.active(), Some(false));
+    }
+
+    #[test]
+    fn leaves_driver_conditions_unknown() {
+        let mut conditionals = Conditionals::new(std::iter::empty());
+
+        track(&mut conditionals, "#ifdef GL_ES");
+        assert_eq!(conditionals.active(), None);
+        track(&mut conditionals, "#define A\n#else");
+        assert_eq!(conditionals.active(), None);
+        track(&mut conditionals, "#endif\n#ifdef A");
+        assert_eq!(conditionals.active(), None);
+        track(&mut conditionals, "#endif\n#if __VERSION__ >= 300");
+        assert_eq!(conditionals.active(), None);
+        track(&mut conditionals, "#ifdef MISSING");
+        assert_eq!(conditionals.active(), Some(false));
+    }
+}
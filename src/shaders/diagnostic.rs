@@ -0,0 +1,301 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Where a line of generated stage source came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineOrigin {
+    /// Added by `ShaderData`, such as the `#version` line.
+    Injected,
+    /// Line `line` (1-based) of `LineMap::files()[file]`.
+    File { file: usize, line: usize },
+}
+
+/// Maps every line of a generated stage back to the file it was read from.
+#[derive(Clone, Debug, Default)]
+pub struct LineMap {
+    files: Vec<PathBuf>,
+    lines: Vec<LineOrigin>,
+}
+
+impl LineMap {
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Records the origin of the next generated line.
+    pub fn push(&mut self, origin: LineOrigin) {
+        self.lines.push(origin);
+    }
+
+    /// Records `count` injected lines in front of the existing ones.
+    pub(super) fn prepend_injected(&mut self, count: usize) {
+        self.lines.splice(0..0, vec![LineOrigin::Injected; count]);
+    }
+
+    /// Returns the index of `path` in `files()`, adding it if needed.
+    pub fn file_index(&mut self, path: &Path) -> usize {
+        match self.files.iter().position(|file| file == path) {
+            Some(index) => index,
+            None => {
+                self.files.push(path.to_path_buf());
+                self.files.len() - 1
+            }
+        }
+    }
+
+    /// Looks up the origin of a 1-based line of the generated source.
+    pub fn origin(&self, line: usize) -> Option<LineOrigin> {
+        line.checked_sub(1)
+            .and_then(|index| self.lines.get(index))
+            .copied()
+    }
+
+    /// Resolves a 1-based generated line to a file and a 1-based line in it.
+    pub fn resolve(&self, line: usize) -> Option<(&Path, usize)> {
+        match self.origin(line)? {
+            LineOrigin::Injected => None,
+            LineOrigin::File { file, line } => Some((self.files[file].as_path(), line)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// One message of a driver info log, pointing into the `.shader` file (or
+/// one of its includes) instead of the generated stage source.
+#[derive(Clone, Debug)]
+pub struct ShaderDiagnostic {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for ShaderDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(
+                f,
+                "{}:{}: {}: {}",
+                self.path.display(),
+                line,
+                self.severity,
+                self.message
+            ),
+            None => write!(
+                f,
+                "{}: {}: {}",
+                self.path.display(),
+                self.severity,
+                self.message
+            ),
+        }
+    }
+}
+
+impl ShaderDiagnostic {
+    /// Rewrites a shader info log into diagnostics for the original files.
+    ///
+    /// Understands the Mesa (`0:12(5): error: ...`), NVIDIA
+    /// (`0(12) : error C0000: ...`) and AMD (`ERROR: 0:12: ...`) formats.
+    /// Lines in another format are kept as messages on `source_path`.
+    pub fn parse_log(log: &str, source_path: &Path, line_map: &LineMap) -> Vec<ShaderDiagnostic> {
+        log.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && *line != "\0")
+            .map(|line| match parse_log_line(line) {
+                Some(located) => {
+                    let (path, line) = match line_map.resolve(located.line) {
+                        Some((path, line)) => (path.to_path_buf(), Some(line)),
+                        None => (source_path.to_path_buf(), None),
+                    };
+                    ShaderDiagnostic {
+                        path,
+                        line,
+                        severity: located.severity,
+                        message: located.message.to_string(),
+                    }
+                }
+                None => ShaderDiagnostic {
+                    path: source_path.to_path_buf(),
+                    line: None,
+                    severity: Severity::Error,
+                    message: line.to_string(),
+                },
+            })
+            .collect()
+    }
+}
+
+struct LocatedLine<'a> {
+    line: usize,
+    severity: Severity,
+    message: &'a str,
+}
+
+fn parse_log_line(line: &str) -> Option<LocatedLine<'_>> {
+    parse_amd(line)
+        .or_else(|| parse_mesa(line))
+        .or_else(|| parse_nvidia(line))
+}
+
+/// `ERROR: 0:12: 'foo' : undeclared identifier`
+fn parse_amd(line: &str) -> Option<LocatedLine<'_>> {
+    let (severity, rest) = if let Some(rest) = line.strip_prefix("ERROR:") {
+        (Severity::Error, rest)
+    } else if let Some(rest) = line.strip_prefix("WARNING:") {
+        (Severity::Warning, rest)
+    } else {
+        return None;
+    };
+
+    let (_, rest) = split_number(rest.trim_start())?;
+    let (line, rest) = split_number(rest.strip_prefix(':')?)?;
+    let message = rest.strip_prefix(':')?.trim();
+
+    Some(LocatedLine {
+        line,
+        severity,
+        message,
+    })
+}
+
+/// `0:12(5): error: syntax error, unexpected ...`
+fn parse_mesa(line: &str) -> Option<LocatedLine<'_>> {
+    let (_, rest) = split_number(line)?;
+    let (line, rest) = split_number(rest.strip_prefix(':')?)?;
+    let rest = rest.strip_prefix('(')?;
+    let (_, rest) = split_number(rest)?;
+    let rest = rest.strip_prefix("):")?.trim_start();
+    let (severity, message) = split_severity(rest)?;
+
+    Some(LocatedLine {
+        line,
+        severity,
+        message: message.strip_prefix(':').unwrap_or(message).trim(),
+    })
+}
+
+/// `0(12) : error C0000: syntax error, unexpected ...`
+fn parse_nvidia(line: &str) -> Option<LocatedLine<'_>> {
+    let (_, rest) = split_number(line)?;
+    let (line, rest) = split_number(rest.strip_prefix('(')?)?;
+    let rest = rest.strip_prefix(')')?.trim_start();
+    let rest = rest.strip_prefix(':')?.trim_start();
+    let (severity, message) = split_severity(rest)?;
+
+    Some(LocatedLine {
+        line,
+        severity,
+        message: message.trim(),
+    })
+}
+
+fn split_number(text: &str) -> Option<(usize, &str)> {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let number = text[..end].parse().ok()?;
+    Some((number, &text[end..]))
+}
+
+fn split_severity(text: &str) -> Option<(Severity, &str)> {
+    [
+        ("error", Severity::Error),
+        ("warning", Severity::Warning),
+        ("note", Severity::Note),
+    ]
+    .iter()
+    .find_map(|(prefix, severity)| text.strip_prefix(prefix).map(|rest| (*severity, rest)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{LineMap, Severity, ShaderDiagnostic};
+    use crate::shaders::{ShaderData, ShaderSource};
+
+    /// The vertex stage of a file whose `oops;` is on line 4, compiled with
+    /// two injected defines in front of it.
+    fn line_map() -> LineMap {
+        let source = "#shader version 330 core\n#shader vertex\nvoid main() {\n  oops;\n}\n";
+        let shaders = ShaderData::builder(ShaderSource::owned("virtual.shader", source.into()))
+            .define("A", "1")
+            .define("B", "")
+            .build()
+            .unwrap();
+        let stage = &shaders.stages[0];
+        assert_eq!(stage.source.lines().nth(4), Some("  oops;"));
+        stage.line_map.clone()
+    }
+
+    fn parse(log: &str) -> Vec<ShaderDiagnostic> {
+        ShaderDiagnostic::parse_log(log, Path::new("virtual.shader"), &line_map())
+    }
+
+    fn assert_points_at_oops(diagnostics: &[ShaderDiagnostic], message: &str) {
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.path, Path::new("virtual.shader"));
+        assert_eq!(diagnostic.line, Some(4));
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.message, message);
+    }
+
+    #[test]
+    fn maps_mesa_lines() {
+        let diagnostics = parse("0:5(3): error: `oops' undeclared\n");
+        assert_points_at_oops(&diagnostics, "`oops' undeclared");
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "virtual.shader:4: error: `oops' undeclared"
+        );
+    }
+
+    #[test]
+    fn maps_nvidia_lines() {
+        let diagnostics = parse("0(5) : error C1008: undefined variable \"oops\"");
+        assert_points_at_oops(&diagnostics, "C1008: undefined variable \"oops\"");
+    }
+
+    #[test]
+    fn maps_amd_lines() {
+        let diagnostics = parse("ERROR: 0:5: 'oops' : undeclared identifier\n\0");
+        assert_points_at_oops(&diagnostics, "'oops' : undeclared identifier");
+    }
+
+    #[test]
+    fn keeps_injected_and_unknown_lines_on_the_file() {
+        let diagnostics = parse(
+            "0:2(1): warning: macro redefined\nERROR: 1 compilation errors.  No code generated.",
+        );
+        assert_eq!(diagnostics.len(), 2);
+
+        assert_eq!(diagnostics[0].line, None);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].message, "macro redefined");
+
+        assert_eq!(diagnostics[1].line, None);
+        assert_eq!(diagnostics[1].severity, Severity::Error);
+        assert_eq!(
+            diagnostics[1].message,
+            "ERROR: 1 compilation errors.  No code generated."
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{ShaderError, StageBuilder};
+
+/// Resolves `#include "relative/path.glsl"` directives for one shader stage.
+///
+/// Paths are resolved relative to the file containing the directive. Every
+/// file is pasted at most once per stage, and a file that ends up including
+/// itself is reported as a cycle.
+pub(super) struct IncludeResolver {
+    /// Canonical paths, in the order the files were first included, so
+    /// dependencies come out the same on every run.
+    included: Vec<PathBuf>,
+    chain: Vec<PathBuf>,
+    canonical_chain: Vec<PathBuf>,
+}
+
+impl IncludeResolver {
+    pub(super) fn new(root: &Path) -> Self {
+        Self {
+            included: vec![],
+            chain: vec![root.to_path_buf()],
+            canonical_chain: vec![fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf())],
+        }
+    }
+
+    /// Canonical paths of every file pasted so far, in include order.
+    pub(super) fn included(&self) -> impl Iterator<Item = &PathBuf> {
+        self.included.iter()
+    }
+
+    /// Returns the include target when `line` is an `#include` directive.
+    pub(super) fn parse_directive(line: &str) -> Option<&str> {
+        let rest = line.trim_start().strip_prefix("#include")?;
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        Some(rest.trim())
+    }
+
+    /// Pastes the file named by an `#include` found at `line` of the current
+    /// file into `out`, recursively resolving its own includes.
+    pub(super) fn include(
+        &mut self,
+        target: &str,
+        line: usize,
+        out: &mut StageBuilder,
+    ) -> Result<(), ShaderError> {
+        let current = self.chain.last().expect("include chain is never empty");
+
+        let relative = match target.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+            Some(relative) if !relative.is_empty() => relative,
+            _ => {
+                return Err(ShaderError::MalformedInclude {
+                    path: current.clone(),
+                    line,
+                    directive: target.to_string(),
+                })
+            }
+        };
+
+        let path = current
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(relative);
+
+        let canonical = match fs::canonicalize(&path) {
+            Ok(canonical) => canonical,
+            Err(error) => return Err(self.not_found(path, error)),
+        };
+
+        if self.canonical_chain.contains(&canonical) {
+            let mut chain = self.chain.clone();
+            chain.push(path);
+            return Err(ShaderError::IncludeCycle { chain });
+        }
+
+        if self.included.contains(&canonical) {
+            return Ok(());
+        }
+        self.included.push(canonical.clone());
+
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(error) => return Err(self.not_found(path, error)),
+        };
+
+        self.chain.push(path.clone());
+        self.canonical_chain.push(canonical);
+
+        for (index, line) in source.lines().enumerate() {
+            match Self::parse_directive(line) {
+                Some(target) if out.active() => self.include(target, index + 1, out)?,
+                _ => out.push_line(line, &path, index + 1),
+            }
+        }
+        self.chain.pop();
+        self.canonical_chain.pop();
+
+        Ok(())
+    }
+
+    fn not_found(&self, path: PathBuf, error: std::io::Error) -> ShaderError {
+        ShaderError::Io {
+            path,
+            chain: self.chain.clone(),
+            error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::super::{ShaderData, ShaderError};
+
+    /// Writes `files` to a fresh directory named after the test.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("gl_object_include_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (name, source) in files {
+            fs::write(dir.join(name), source).unwrap();
+        }
+        dir
+    }
+
+    fn build(path: &Path) -> Result<ShaderData, ShaderError> {
+        ShaderData::new(path.to_str().unwrap())
+    }
+
+    fn file_names(chain: &[PathBuf]) -> Vec<&str> {
+        chain
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn reports_include_cycle_with_chain() {
+        let dir = write_files(
+            "cycle",
+            &[
+                ("a.shader", "#shader vertex\n#include \"b.glsl\"\n"),
+                ("b.glsl", "#include \"c.glsl\"\n"),
+                ("c.glsl", "#include \"b.glsl\"\n"),
+            ],
+        );
+
+        let error = build(&dir.join("a.shader")).err().unwrap();
+        let message = error.to_string();
+        match error {
+            ShaderError::IncludeCycle { chain } => {
+                assert_eq!(
+                    file_names(&chain),
+                    ["a.shader", "b.glsl", "c.glsl", "b.glsl"]
+                )
+            }
+            other => panic!("expected an include cycle, got {:?}", other),
+        }
+        assert!(message.starts_with("include cycle: "), "{}", message);
+        assert!(message.contains("c.glsl -> "), "{}", message);
+    }
+
+    #[test]
+    fn reports_file_including_itself() {
+        let dir = write_files(
+            "self",
+            &[("a.shader", "#shader vertex\n#include \"a.shader\"\n")],
+        );
+
+        match build(&dir.join("a.shader")) {
+            Err(ShaderError::IncludeCycle { chain }) => {
+                assert_eq!(file_names(&chain), ["a.shader", "a.shader"])
+            }
+            other => panic!("expected an include cycle, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn includes_each_file_once_per_stage() {
+        let dir = write_files(
+            "once",
+            &[
+                (
+                    "a.shader",
+                    "#shader vertex\n#include \"b.glsl\"\n#include \"c.glsl\"\n\
+                     #shader fragment\n#include \"b.glsl\"\n",
+                ),
+                ("b.glsl", "float shared;\n"),
+                ("c.glsl", "#include \"b.glsl\"\nfloat other;\n"),
+            ],
+        );
+
+        let shaders = build(&dir.join("a.shader")).unwrap();
+        for stage in shaders.stages.iter() {
+            assert_eq!(stage.source.matches("float shared;").count(), 1);
+        }
+        assert_eq!(shaders.stages[0].source.matches("float other;").count(), 1);
+        assert_eq!(shaders.dependencies.len(), 3);
+    }
+
+    #[test]
+    fn lists_dependencies_in_include_order() {
+        let dir = write_files(
+            "order",
+            &[
+                (
+                    "a.shader",
+                    "#shader vertex\n#include \"d.glsl\"\n#include \"b.glsl\"\n\
+                     #shader fragment\n#include \"c.glsl\"\n#include \"d.glsl\"\n",
+                ),
+                ("b.glsl", "#include \"e.glsl\"\n"),
+                ("c.glsl", ""),
+                ("d.glsl", ""),
+                ("e.glsl", ""),
+            ],
+        );
+
+        let shaders = build(&dir.join("a.shader")).unwrap();
+        assert_eq!(
+            file_names(&shaders.dependencies),
+            ["a.shader", "d.glsl", "b.glsl", "e.glsl", "c.glsl"]
+        );
+    }
+
+    #[test]
+    fn maps_included_lines_to_their_file() {
+        let dir = write_files(
+            "lines",
+            &[
+                (
+                    "a.shader",
+                    "#shader vertex\nfloat a;\n#include \"b.glsl\"\nfloat c;\n",
+                ),
+                ("b.glsl", "float b0;\nfloat b1;\n"),
+            ],
+        );
+
+        let shaders = build(&dir.join("a.shader")).unwrap();
+        let stage = &shaders.stages[0];
+        let lines: Vec<&str> = stage.source.lines().collect();
+        let line = |text: &str| lines.iter().position(|line| *line == text).unwrap() + 1;
+
+        let (path, number) = stage.line_map.resolve(line("float b1;")).unwrap();
+        assert_eq!(path.file_name().unwrap(), "b.glsl");
+        assert_eq!(number, 2);
+        let (path, number) = stage.line_map.resolve(line("float c;")).unwrap();
+        assert_eq!(path.file_name().unwrap(), "a.shader");
+        assert_eq!(number, 4);
+        assert_eq!(stage.line_map.resolve(1), None);
+    }
+
+    #[test]
+    fn skips_includes_in_disabled_sections() {
+        let dir = write_files(
+            "disabled",
+            &[(
+                "a.shader",
+                "#shader vertex\n#ifdef MISSING\n#include \"missing.glsl\"\n#endif\n",
+            )],
+        );
+
+        let shaders = build(&dir.join("a.shader")).unwrap();
+        assert!(shaders.stages[0]
+            .source
+            .contains("#include \"missing.glsl\""));
+        assert!(matches!(
+            ShaderData::builder(dir.join("a.shader").to_str().unwrap())
+                .define("MISSING", "")
+                .build(),
+            Err(ShaderError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_unquoted_include() {
+        let dir = write_files(
+            "malformed",
+            &[("a.shader", "#shader vertex\n#include <b.glsl>\n")],
+        );
+
+        match build(&dir.join("a.shader")) {
+            Err(ShaderError::MalformedInclude {
+                line, directive, ..
+            }) => {
+                assert_eq!(line, 2);
+                assert_eq!(directive, "<b.glsl>");
+            }
+            other => panic!("expected a malformed include, got {:?}", other.err()),
+        }
+    }
+}
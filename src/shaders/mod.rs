@@ -0,0 +1,549 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use self::conditional::Conditionals;
+use self::diagnostic::{LineMap, LineOrigin, ShaderDiagnostic};
+use self::include::IncludeResolver;
+pub use self::source::ShaderSource;
+use self::version::{GlslProfile, GlslVersion};
+
+mod conditional;
+pub mod diagnostic;
+mod include;
+pub mod source;
+#[cfg(feature = "translate")]
+pub mod translate;
+#[cfg(feature = "validate")]
+pub mod validate;
+pub mod version;
+pub mod watcher;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderType {
+    None,
+    Fragment,
+    Vertex,
+    Geometry,
+    TessControl,
+    TessEvaluation,
+    Compute,
+}
+
+impl ShaderType {
+    /// Parses the name following `#shader`.
+    pub fn from_name(name: &str) -> Option<ShaderType> {
+        match name {
+            "vertex" => Some(ShaderType::Vertex),
+            "fragment" => Some(ShaderType::Fragment),
+            "geometry" => Some(ShaderType::Geometry),
+            "tess_control" => Some(ShaderType::TessControl),
+            "tess_evaluation" => Some(ShaderType::TessEvaluation),
+            "compute" => Some(ShaderType::Compute),
+            _ => None,
+        }
+    }
+
+    /// The `glow::*_SHADER` constant passed to `create_shader`.
+    pub fn gl_type(&self) -> Option<u32> {
+        match self {
+            ShaderType::None => None,
+            ShaderType::Fragment => Some(glow::FRAGMENT_SHADER),
+            ShaderType::Vertex => Some(glow::VERTEX_SHADER),
+            ShaderType::Geometry => Some(glow::GEOMETRY_SHADER),
+            ShaderType::TessControl => Some(glow::TESS_CONTROL_SHADER),
+            ShaderType::TessEvaluation => Some(glow::TESS_EVALUATION_SHADER),
+            ShaderType::Compute => Some(glow::COMPUTE_SHADER),
+        }
+    }
+}
+
+pub struct Shader {
+    pub source: String,
+    pub shader_type: ShaderType,
+    /// Origin of every line of `source`, used to point driver errors back
+    /// into the `.shader` file.
+    pub line_map: LineMap,
+}
+
+/// Generated source of one stage while a `.shader` file is being split.
+struct StageBuilder {
+    shader_type: ShaderType,
+    source: String,
+    line_map: LineMap,
+    conditionals: Conditionals,
+}
+
+impl StageBuilder {
+    fn new(shader_type: ShaderType, defines: &[(String, String)]) -> Self {
+        Self {
+            shader_type,
+            source: String::new(),
+            line_map: LineMap::default(),
+            conditionals: Conditionals::new(defines.iter().map(|(name, _)| name.as_str())),
+        }
+    }
+
+    /// Whether the next line can reach the compiler, i.e. it is not inside
+    /// an `#ifdef` section the defines rule out.
+    fn active(&self) -> bool {
+        self.conditionals.active() != Some(false)
+    }
+
+    fn push_line(&mut self, line: &str, path: &Path, line_number: usize) {
+        self.conditionals.track(line);
+        self.source += line;
+        self.source += "\n";
+        let file = self.line_map.file_index(path);
+        self.line_map.push(LineOrigin::File {
+            file,
+            line: line_number,
+        });
+    }
+
+    /// Finishes the stage, putting the `header` lines (such as `#version`)
+    /// in front of the collected source.
+    fn build(mut self, header: &[String]) -> Shader {
+        let mut source = String::new();
+        for line in header {
+            source += line;
+            source += "\n";
+        }
+        self.line_map.prepend_injected(header.len());
+
+        Shader {
+            source: source + &self.source,
+            shader_type: self.shader_type,
+            line_map: self.line_map,
+        }
+    }
+}
+
+pub struct ShaderData {
+    /// Path of the `.shader` file, or the name of an in-memory source.
+    pub source_path: String,
+    /// The `#version` every stage is emitted with.
+    pub version: GlslVersion,
+    /// `#define`s injected after the version line, see
+    /// `ShaderDataBuilder::define`.
+    pub defines: Vec<(String, String)>,
+    /// Stages in the order their first `#shader` section appears.
+    pub stages: Vec<Shader>,
+    /// The `.shader` file (unless it lives in memory) and every file it
+    /// includes, in the order they are first included.
+    pub dependencies: Vec<PathBuf>,
+}
+
+/// Options for splitting a `.shader` file, see `ShaderData::builder`.
+pub struct ShaderDataBuilder {
+    source: ShaderSource,
+    profile: Option<GlslProfile>,
+    defines: Vec<(String, String)>,
+}
+
+/// A set of stages that cannot be linked into one program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StageCombination {
+    /// The file has no `#shader` sections.
+    Empty,
+    /// Compute shaders must be linked on their own.
+    ComputeWithGraphics,
+    /// Graphics programs need a vertex stage.
+    MissingVertex,
+    /// A `tess_control` stage needs a matching `tess_evaluation` stage.
+    TessControlWithoutEvaluation,
+}
+
+impl fmt::Display for StageCombination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageCombination::Empty => write!(f, "no #shader sections"),
+            StageCombination::ComputeWithGraphics => {
+                write!(f, "compute shaders cannot be mixed with graphics stages")
+            }
+            StageCombination::MissingVertex => write!(f, "missing a vertex stage"),
+            StageCombination::TessControlWithoutEvaluation => {
+                write!(f, "tess_control stage without a tess_evaluation stage")
+            }
+        }
+    }
+}
+
+impl StageCombination {
+    /// Checks that `stages` can be linked into one program.
+    pub fn check(stages: &[ShaderType]) -> Result<(), StageCombination> {
+        let has = |shader_type| stages.contains(&shader_type);
+
+        if stages.is_empty() {
+            Err(StageCombination::Empty)
+        } else if has(ShaderType::Compute) {
+            match stages.len() {
+                1 => Ok(()),
+                _ => Err(StageCombination::ComputeWithGraphics),
+            }
+        } else if !has(ShaderType::Vertex) {
+            Err(StageCombination::MissingVertex)
+        } else if has(ShaderType::TessControl) && !has(ShaderType::TessEvaluation) {
+            Err(StageCombination::TessControlWithoutEvaluation)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    /// A shader file could not be read. `chain` lists the files that led to
+    /// it, starting with the `.shader` file itself.
+    Io {
+        path: PathBuf,
+        chain: Vec<PathBuf>,
+        error: std::io::Error,
+    },
+    /// A file ended up including one of the files that included it.
+    IncludeCycle { chain: Vec<PathBuf> },
+    /// An `#include` whose target is not a quoted path.
+    MalformedInclude {
+        path: PathBuf,
+        line: usize,
+        directive: String,
+    },
+    UnknownShaderType {
+        path: PathBuf,
+        line: usize,
+        name: String,
+    },
+    /// The stages in the file cannot be linked together.
+    IllegalStages {
+        path: PathBuf,
+        combination: StageCombination,
+    },
+    /// A `#shader version` line that is not `<number> [core|compatibility|es]`.
+    MalformedVersion {
+        path: PathBuf,
+        line: usize,
+        text: String,
+    },
+    /// The file needs a newer GLSL version than the context supports.
+    UnsupportedVersion {
+        path: PathBuf,
+        requested: GlslVersion,
+        supported: GlslVersion,
+    },
+    /// The driver rejected a stage.
+    Compile {
+        path: PathBuf,
+        diagnostics: Vec<ShaderDiagnostic>,
+    },
+    /// The driver could not link the compiled stages.
+    Link { path: PathBuf, log: String },
+    /// The driver could not create a shader or program object.
+    Create { path: PathBuf, error: String },
+}
+
+fn format_chain(chain: &[PathBuf]) -> String {
+    chain
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Io { path, chain, error } if chain.is_empty() => {
+                write!(f, "cannot read {}: {}", path.display(), error)
+            }
+            ShaderError::Io { path, chain, error } => write!(
+                f,
+                "cannot read {}: {} (included from {})",
+                path.display(),
+                error,
+                format_chain(chain)
+            ),
+            ShaderError::IncludeCycle { chain } => {
+                write!(f, "include cycle: {}", format_chain(chain))
+            }
+            ShaderError::MalformedInclude {
+                path,
+                line,
+                directive,
+            } => write!(
+                f,
+                "{}:{}: expected #include \"<path>\", found #include {}",
+                path.display(),
+                line,
+                directive
+            ),
+            ShaderError::UnknownShaderType { path, line, name } => {
+                write!(
+                    f,
+                    "{}:{}: unknown shader type {:?}",
+                    path.display(),
+                    line,
+                    name
+                )
+            }
+            ShaderError::IllegalStages { path, combination } => {
+                write!(f, "{}: {}", path.display(), combination)
+            }
+            ShaderError::MalformedVersion { path, line, text } => write!(
+                f,
+                "{}:{}: expected #shader version <number> [core|compatibility|es], found {:?}",
+                path.display(),
+                line,
+                text
+            ),
+            ShaderError::UnsupportedVersion {
+                path,
+                requested,
+                supported,
+            } => write!(
+                f,
+                "{}: needs GLSL {} but the context only supports GLSL {}",
+                path.display(),
+                requested,
+                supported
+            ),
+            ShaderError::Compile { path, diagnostics } => {
+                write!(f, "{}: shader compilation failed", path.display())?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n{}", diagnostic)?;
+                }
+                Ok(())
+            }
+            ShaderError::Link { path, log } => {
+                write!(f, "{}: program link failed: {}", path.display(), log.trim())
+            }
+            ShaderError::Create { path, error } => {
+                write!(f, "{}: cannot create GL object: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShaderError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl ShaderData {
+    /// Splits a `.shader` file into its stages and resolves `#include`
+    /// directives relative to the file that contains them.
+    pub fn new(source: impl Into<ShaderSource>) -> Result<ShaderData, ShaderError> {
+        ShaderData::builder(source).build()
+    }
+
+    pub fn builder(source: impl Into<ShaderSource>) -> ShaderDataBuilder {
+        ShaderDataBuilder {
+            source: source.into(),
+            profile: None,
+            defines: vec![],
+        }
+    }
+
+    /// Fails when `gl` cannot compile the GLSL version of this file.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn check_context(&self, gl: &glow::Context) -> Result<(), ShaderError> {
+        let supported = GlslVersion::supported_by(gl);
+        if self.version.fits(&supported) {
+            Ok(())
+        } else {
+            Err(ShaderError::UnsupportedVersion {
+                path: PathBuf::from(&self.source_path),
+                requested: self.version,
+                supported,
+            })
+        }
+    }
+
+    pub fn stage(&self, shader_type: ShaderType) -> Option<&Shader> {
+        self.stages
+            .iter()
+            .find(|stage| stage.shader_type == shader_type)
+    }
+
+    /// Names of the plain uniforms declared in any stage, skipping sections
+    /// the preprocessor drops. Uniform blocks are not included.
+    pub fn declared_uniforms(&self) -> Vec<String> {
+        let mut names = vec![];
+        for stage in self.stages.iter() {
+            let mut conditionals = Conditionals::new(std::iter::empty());
+            for line in stage.source.lines() {
+                conditionals.track(line);
+                if conditionals.active() == Some(false) {
+                    continue;
+                }
+                for name in uniform_names(line) {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+        names
+    }
+}
+
+/// Names declared by a `uniform <type> <name>, ...;` line, with array sizes
+/// and initializers dropped.
+fn uniform_names(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let declaration = match line.find("uniform ") {
+        Some(start) if start == 0 || line.starts_with("layout") => &line[start + 8..],
+        _ => return vec![],
+    };
+    if declaration.contains('{') {
+        return vec![];
+    }
+
+    let declaration = declaration.split(';').next().unwrap_or("");
+    let mut words = declaration
+        .split_whitespace()
+        .skip_while(|word| matches!(*word, "lowp" | "mediump" | "highp"));
+    words.next();
+    let names = words.collect::<Vec<_>>().join(" ");
+
+    names
+        .split(',')
+        .filter_map(|name| {
+            let name: String = name
+                .trim()
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect();
+            let identifier = name.starts_with(|c: char| c.is_alphabetic() || c == '_');
+            identifier.then_some(name)
+        })
+        .collect()
+}
+
+impl ShaderDataBuilder {
+    /// Emits the stages for `profile`, converting the version requested by
+    /// the file (or the platform default) to the closest one of `profile`.
+    pub fn profile(mut self, profile: GlslProfile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Injects `#define name value` after the version line of every stage,
+    /// replacing an earlier define of the same name. `value` may be empty.
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.defines.retain(|(defined, _)| defined != name);
+        self.defines.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn defines<N: AsRef<str>, V: AsRef<str>>(self, defines: &[(N, V)]) -> Self {
+        defines.iter().fold(self, |builder, (name, value)| {
+            builder.define(name.as_ref(), value.as_ref())
+        })
+    }
+
+    pub fn build(self) -> Result<ShaderData, ShaderError> {
+        let path = Path::new(self.source.name());
+        let source = self.source.read()?;
+
+        let mut version = None;
+        let mut stages: Vec<(StageBuilder, IncludeResolver)> = vec![];
+        let mut current = None;
+
+        for (index, line) in source.lines().enumerate() {
+            if let Some(name) = line.strip_prefix("#shader") {
+                if let Some(text) = name.trim().strip_prefix("version") {
+                    version = Some(GlslVersion::parse(text).ok_or_else(|| {
+                        ShaderError::MalformedVersion {
+                            path: path.to_path_buf(),
+                            line: index + 1,
+                            text: text.trim().to_string(),
+                        }
+                    })?);
+                    continue;
+                }
+
+                let shader_type = ShaderType::from_name(name.trim()).ok_or_else(|| {
+                    ShaderError::UnknownShaderType {
+                        path: path.to_path_buf(),
+                        line: index + 1,
+                        name: name.trim().to_string(),
+                    }
+                })?;
+
+                current = match stages
+                    .iter()
+                    .position(|(s, _)| s.shader_type == shader_type)
+                {
+                    Some(position) => Some(position),
+                    None => {
+                        stages.push((
+                            StageBuilder::new(shader_type, &self.defines),
+                            IncludeResolver::new(path),
+                        ));
+                        Some(stages.len() - 1)
+                    }
+                };
+            } else if let Some((stage, includes)) = current.map(|position| &mut stages[position]) {
+                match IncludeResolver::parse_directive(line) {
+                    Some(target) if stage.active() => includes.include(target, index + 1, stage)?,
+                    _ => stage.push_line(line, path, index + 1),
+                }
+            }
+        }
+
+        let shader_types: Vec<ShaderType> = stages.iter().map(|(s, _)| s.shader_type).collect();
+        StageCombination::check(&shader_types).map_err(|combination| {
+            ShaderError::IllegalStages {
+                path: path.to_path_buf(),
+                combination,
+            }
+        })?;
+
+        let mut version = version.unwrap_or_else(GlslVersion::platform_default);
+        if let Some(profile) = self.profile {
+            version = version.with_profile(profile);
+        }
+
+        let mut dependencies: Vec<PathBuf> = self
+            .source
+            .file_path()
+            .map(Path::to_path_buf)
+            .into_iter()
+            .collect();
+        for (_, includes) in stages.iter() {
+            for file in includes.included() {
+                if !dependencies.contains(file) {
+                    dependencies.push(file.clone());
+                }
+            }
+        }
+
+        let stages = stages
+            .into_iter()
+            .map(|(stage, _)| {
+                let mut header = vec![version.directive()];
+                header.extend(self.defines.iter().map(|(name, value)| {
+                    format!("#define {} {}", name, value).trim_end().to_string()
+                }));
+                // GLSL ES has no default float precision in fragment shaders.
+                if version.profile == GlslProfile::Es && stage.shader_type == ShaderType::Fragment {
+                    header.push("precision mediump float;".to_string());
+                }
+                stage.build(&header)
+            })
+            .collect();
+
+        Ok(ShaderData {
+            source_path: self.source.name().to_string(),
+            version,
+            defines: self.defines,
+            stages,
+            dependencies,
+        })
+    }
+}
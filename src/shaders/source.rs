@@ -0,0 +1,103 @@
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::ShaderError;
+
+/// Where the text of a `.shader` file comes from.
+///
+/// In-memory sources carry a virtual name that stands in for the path in
+/// error messages and as the base `#include` paths are resolved against, so
+/// their includes are still read from disk.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ShaderSource {
+    /// A `.shader` file read from disk.
+    File(String),
+    /// Source compiled into the binary, usually with `include_str!`.
+    Static {
+        name: &'static str,
+        source: &'static str,
+    },
+    /// Source built at runtime.
+    Owned { name: String, source: String },
+}
+
+impl ShaderSource {
+    pub fn file(path: &str) -> Self {
+        ShaderSource::File(path.to_string())
+    }
+
+    /// Embeds a `.shader` file in the binary:
+    ///
+    /// ```ignore
+    /// ShaderSource::embedded("shader.shader", include_str!("shader.shader"))
+    /// ```
+    pub fn embedded(name: &'static str, source: &'static str) -> Self {
+        ShaderSource::Static { name, source }
+    }
+
+    pub fn owned(name: &str, source: String) -> Self {
+        ShaderSource::Owned {
+            name: name.to_string(),
+            source,
+        }
+    }
+
+    /// The file path, or the virtual name of an in-memory source.
+    pub fn name(&self) -> &str {
+        match self {
+            ShaderSource::File(path) => path,
+            ShaderSource::Static { name, .. } => name,
+            ShaderSource::Owned { name, .. } => name,
+        }
+    }
+
+    /// The path on disk, `None` for in-memory sources.
+    pub fn file_path(&self) -> Option<&Path> {
+        match self {
+            ShaderSource::File(path) => Some(Path::new(path)),
+            _ => None,
+        }
+    }
+
+    /// Reads the text of the source, touching the disk only for files.
+    pub fn read(&self) -> Result<Cow<'_, str>, ShaderError> {
+        match self {
+            ShaderSource::File(path) => {
+                fs::read_to_string(path)
+                    .map(Cow::Owned)
+                    .map_err(|error| ShaderError::Io {
+                        path: PathBuf::from(path),
+                        chain: vec![],
+                        error,
+                    })
+            }
+            ShaderSource::Static { source, .. } => Ok(Cow::Borrowed(source)),
+            ShaderSource::Owned { source, .. } => Ok(Cow::Borrowed(source)),
+        }
+    }
+}
+
+impl From<&str> for ShaderSource {
+    fn from(path: &str) -> Self {
+        ShaderSource::file(path)
+    }
+}
+
+impl From<String> for ShaderSource {
+    fn from(path: String) -> Self {
+        ShaderSource::File(path)
+    }
+}
+
+impl From<&String> for ShaderSource {
+    fn from(path: &String) -> Self {
+        ShaderSource::file(path)
+    }
+}
+
+impl From<&ShaderSource> for ShaderSource {
+    fn from(source: &ShaderSource) -> Self {
+        source.clone()
+    }
+}
@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use naga::back::{spv, wgsl};
+
+use super::diagnostic::{Severity, ShaderDiagnostic};
+use super::validate::{assign_bindings, naga_source, parse_stage, Layouts};
+use super::{ShaderData, ShaderError, ShaderSource, ShaderType};
+
+/// One stage of a `.shader` file in the formats of other renderers.
+pub struct Translation {
+    pub shader_type: ShaderType,
+    /// SPIR-V words, with the entry point named `main`.
+    pub spirv: Vec<u32>,
+    pub wgsl: String,
+}
+
+impl Translation {
+    /// Extension the stage is written with, e.g. `vert` for
+    /// `shader.vert.spv`.
+    pub fn extension(&self) -> &'static str {
+        match self.shader_type {
+            ShaderType::Vertex => "vert",
+            ShaderType::Fragment => "frag",
+            ShaderType::Compute => "comp",
+            ShaderType::Geometry => "geom",
+            ShaderType::TessControl => "tesc",
+            ShaderType::TessEvaluation => "tese",
+            ShaderType::None => "glsl",
+        }
+    }
+}
+
+/// Translates every stage of a `.shader` file to SPIR-V and WGSL with naga.
+///
+/// Stages naga rejects are reported as `ShaderError::Compile` with
+/// diagnostics pointing into the `.shader` file, as driver errors are.
+/// naga cannot translate geometry and tessellation stages.
+pub fn translate(source: impl Into<ShaderSource>) -> Result<Vec<Translation>, ShaderError> {
+    let shader_data = ShaderData::new(source)?;
+    let source_path = Path::new(&shader_data.source_path);
+    let mut layouts = Layouts::default();
+    let mut translations = vec![];
+
+    for stage in shader_data.stages.iter() {
+        let mut diagnostics = vec![];
+        let text = assign_bindings(&naga_source(stage), stage.shader_type, &mut layouts);
+
+        let stage_error = |message: String| ShaderDiagnostic {
+            path: source_path.to_path_buf(),
+            line: None,
+            severity: Severity::Error,
+            message: format!("{:?} stage: {}", stage.shader_type, message),
+        };
+
+        if !matches!(
+            stage.shader_type,
+            ShaderType::Vertex | ShaderType::Fragment | ShaderType::Compute
+        ) {
+            return Err(ShaderError::Compile {
+                path: source_path.to_path_buf(),
+                diagnostics: vec![stage_error("naga cannot translate this stage".to_string())],
+            });
+        }
+
+        let result = parse_stage(stage, &text, source_path, true, &mut diagnostics).and_then(
+            |(module, info)| {
+                let spirv = spv::write_vec(&module, &info, &spv::Options::default(), None)
+                    .map_err(|error| diagnostics.push(stage_error(error.to_string())))
+                    .ok()?;
+                let wgsl = wgsl::write_string(&module, &info, wgsl::WriterFlags::empty())
+                    .map_err(|error| diagnostics.push(stage_error(error.to_string())))
+                    .ok()?;
+                Some(Translation {
+                    shader_type: stage.shader_type,
+                    spirv,
+                    wgsl,
+                })
+            },
+        );
+
+        match result {
+            Some(translation) => translations.push(translation),
+            None => {
+                if diagnostics.is_empty() {
+                    diagnostics.push(stage_error("rejected by naga".to_string()));
+                }
+                return Err(ShaderError::Compile {
+                    path: source_path.to_path_buf(),
+                    diagnostics,
+                });
+            }
+        }
+    }
+
+    Ok(translations)
+}
+
+/// Translates the `.shader` file at `path` and writes
+/// `<name>.<stage>.spv` and `<name>.<stage>.wgsl` next to it, returning the
+/// written paths.
+pub fn translate_file(path: &str) -> Result<Vec<PathBuf>, ShaderError> {
+    let translations = translate(path)?;
+    let input = Path::new(path);
+    let stem = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut written = vec![];
+    for translation in translations.iter() {
+        let spirv: Vec<u8> = translation
+            .spirv
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect();
+        let outputs = [
+            ("spv", spirv),
+            ("wgsl", translation.wgsl.clone().into_bytes()),
+        ];
+
+        for (format, bytes) in outputs {
+            let output =
+                input.with_file_name(format!("{}.{}.{}", stem, translation.extension(), format));
+            fs::write(&output, bytes).map_err(|error| ShaderError::Io {
+                path: output.clone(),
+                chain: vec![],
+                error,
+            })?;
+            written.push(output);
+        }
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::translate;
+    use crate::shaders::{ShaderError, ShaderSource, ShaderType};
+
+    fn assert_translates(path: &str) {
+        let translations = translate(path).unwrap_or_else(|error| panic!("{}", error));
+        let stages: Vec<ShaderType> = translations
+            .iter()
+            .map(|translation| translation.shader_type)
+            .collect();
+        assert_eq!(stages, [ShaderType::Vertex, ShaderType::Fragment]);
+        assert!(translations[0].wgsl.contains("u_view_projection"));
+    }
+
+    #[test]
+    fn translates_shaders_using_the_camera_block() {
+        assert_translates("resources/shader_with_matrix.shader");
+    }
+
+    #[test]
+    fn translates_instanced_shaders() {
+        assert_translates("resources/instanced.shader");
+    }
+
+    #[test]
+    fn reports_vulkan_only_errors() {
+        // Declarations that do not start their line get no binding
+        let source = "#shader vertex\n/* camera */ uniform Block {\n  mat4 m;\n};\n\
+                      void main() {\n  gl_Position = m * vec4(0.0);\n}\n";
+
+        match translate(ShaderSource::owned("block.shader", source.to_string())) {
+            Err(ShaderError::Compile { diagnostics, .. }) => {
+                assert!(!diagnostics.is_empty());
+                assert!(
+                    diagnostics
+                        .iter()
+                        .any(|diagnostic| diagnostic.message.contains("binding")),
+                    "{:?}",
+                    diagnostics
+                );
+            }
+            other => panic!("expected a compile error, got {:?}", other.err()),
+        }
+    }
+}
@@ -0,0 +1,582 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use naga::front::glsl::{ErrorKind, Options, Parser};
+use naga::valid::{Capabilities, ModuleInfo, ValidationFlags, Validator};
+use naga::{Module, ShaderStage};
+
+use super::diagnostic::{Severity, ShaderDiagnostic};
+use super::{Shader, ShaderData, ShaderError, ShaderSource, ShaderType};
+
+/// naga only parses GLSL 440 and newer, so stages are checked as 450 core.
+/// The replacement keeps line numbers, the directive is always line 1.
+const NAGA_VERSION: &str = "#version 450 core";
+
+fn naga_stage(shader_type: ShaderType) -> Option<ShaderStage> {
+    match shader_type {
+        ShaderType::Vertex => Some(ShaderStage::Vertex),
+        ShaderType::Fragment => Some(ShaderStage::Fragment),
+        ShaderType::Compute => Some(ShaderStage::Compute),
+        _ => None,
+    }
+}
+
+/// naga targets Vulkan and rejects some GLSL that is fine for OpenGL, such as
+/// uniforms without `layout(binding = N)` that `assign_bindings` left alone
+/// because their declaration does not start the line.
+fn vulkan_only(kind: &ErrorKind) -> bool {
+    match kind {
+        ErrorKind::SemanticError(message) => {
+            message.contains("layout(binding") || message.contains("layout(location")
+        }
+        _ => false,
+    }
+}
+
+/// Replaces the version line of `stage` with one naga parses. The version
+/// directive is always line 1, so line numbers are kept.
+pub(super) fn naga_source(stage: &Shader) -> String {
+    let mut lines = stage.source.lines();
+    lines.next();
+    std::iter::once(NAGA_VERSION)
+        .chain(lines)
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+/// Splits a declaration such as `layout(std140) uniform Camera {` into the
+/// qualifiers of its layout, if it has one, and the rest of the line.
+fn split_layout(declaration: &str) -> Option<(Option<&str>, &str)> {
+    let rest = match declaration.strip_prefix("layout") {
+        Some(rest) => rest.trim_start().strip_prefix('(')?,
+        None => return Some((None, declaration)),
+    };
+    let (qualifiers, rest) = rest.split_once(')')?;
+    Some((Some(qualifiers.trim()), rest.trim_start()))
+}
+
+/// The value of `key` in the qualifiers of a layout, e.g. 3 for `binding`
+/// in `std140, binding = 3`.
+fn layout_value(qualifiers: Option<&str>, key: &str) -> Option<u32> {
+    qualifiers?.split(',').find_map(|qualifier| {
+        let (name, value) = qualifier.split_once('=')?;
+        (name.trim() == key).then(|| value.trim().parse().ok())?
+    })
+}
+
+fn has_layout_key(qualifiers: Option<&str>, key: &str) -> bool {
+    qualifiers.is_some_and(|qualifiers| {
+        qualifiers
+            .split(',')
+            .any(|qualifier| qualifier.trim().starts_with(key))
+    })
+}
+
+/// Qualifiers that may come before `in` or `out`, or between them and the
+/// type.
+const INTERFACE_QUALIFIERS: [&str; 8] = [
+    "flat",
+    "smooth",
+    "noperspective",
+    "centroid",
+    "sample",
+    "highp",
+    "mediump",
+    "lowp",
+];
+
+/// An `in` or `out` variable declared on its own line, such as
+/// `flat out vec3 normal;`.
+struct InterfaceVariable<'a> {
+    output: bool,
+    gl_type: &'a str,
+    name: &'a str,
+    /// Number of array elements, 1 for single values.
+    elements: u32,
+}
+
+impl InterfaceVariable<'_> {
+    /// Columns and rows of a float matrix that is not an array, such as 4
+    /// and 3 for a `mat4x3`.
+    fn matrix(&self) -> Option<(u32, u32)> {
+        if self.elements != 1 {
+            return None;
+        }
+        let size = self.gl_type.strip_prefix("mat")?;
+        let (columns, rows) = size.split_once('x').unwrap_or((size, size));
+        Some((columns.parse().ok()?, rows.parse().ok()?))
+    }
+
+    /// Consecutive locations the variable takes, one per matrix column and
+    /// array element.
+    fn locations(&self) -> u32 {
+        let columns = self
+            .gl_type
+            .strip_prefix("mat")
+            .or_else(|| self.gl_type.strip_prefix("dmat"))
+            .and_then(|size| size.get(..1)?.parse().ok())
+            .unwrap_or(1);
+        columns * self.elements
+    }
+}
+
+/// Parses the part of an `in` or `out` declaration after its layout.
+/// `None` for other lines and for interface blocks.
+fn interface_variable(declaration: &str) -> Option<InterfaceVariable<'_>> {
+    let mut words = declaration
+        .strip_suffix(';')?
+        .split_whitespace()
+        .skip_while(|word| INTERFACE_QUALIFIERS.contains(word));
+    let output = match words.next()? {
+        "in" => false,
+        "out" => true,
+        _ => return None,
+    };
+    let mut words = words.skip_while(|word| INTERFACE_QUALIFIERS.contains(word));
+    let gl_type = words.next()?;
+    let declarator = words.next()?;
+    if words.next().is_some() || declarator.contains([',', '{', '(']) {
+        return None;
+    }
+
+    let (name, elements) = match declarator.split_once('[') {
+        Some((name, size)) => (name, size.strip_suffix(']')?.parse().ok()?),
+        None => (declarator, 1),
+    };
+    Some(InterfaceVariable {
+        output,
+        gl_type,
+        name,
+        elements,
+    })
+}
+
+/// `rest` of a declaration with `assignment` merged into its layout
+/// `qualifiers`, if it has any.
+fn with_layout(assignment: &str, qualifiers: Option<&str>, rest: &str) -> String {
+    match qualifiers {
+        Some(qualifiers) if !qualifiers.is_empty() => {
+            format!("layout({}, {}) {}", assignment, qualifiers, rest)
+        }
+        _ => format!("layout({}) {}", assignment, rest),
+    }
+}
+
+/// Replaces the identifier `name` wherever it stands on its own in `text`.
+fn replace_identifier(text: &str, name: &str, replacement: &str) -> String {
+    let is_identifier = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (start, _) in text.match_indices(name) {
+        let end = start + name.len();
+        if text[..start].ends_with(is_identifier) || text[end..].starts_with(is_identifier) {
+            continue;
+        }
+        out += &text[copied..start];
+        out += replacement;
+        copied = end;
+    }
+    out += &text[copied..];
+    out
+}
+
+/// Bindings and locations handed out to the stages of one `.shader` file.
+#[derive(Default)]
+pub(super) struct Layouts {
+    /// Binding of every uniform declaration; the same declaration in several
+    /// stages shares one.
+    bindings: HashMap<String, u32>,
+    /// Location of every variable passed from one stage to the next, by
+    /// name, so the output and the input reading it agree.
+    varyings: HashMap<String, u32>,
+    next_varying: u32,
+}
+
+/// naga targets Vulkan and needs a binding for every resource and a location
+/// for every stage input and output, which OpenGL shaders usually leave to
+/// `glGetUniformLocation` and `glGetAttribLocation`.
+///
+/// Uniform declarations without a binding get the next free one. Vertex
+/// inputs and fragment outputs without a location are numbered in order,
+/// after any explicit location in the stage; variables passed between stages
+/// share a location by name. Locations and bindings are merged into the
+/// layout of a declaration when it has one. naga takes no matrix inputs, so
+/// those are read as one vector per column and rebuilt where they are used.
+/// Lines are only rewritten in place, so line numbers are kept.
+pub(super) fn assign_bindings(
+    text: &str,
+    shader_type: ShaderType,
+    layouts: &mut Layouts,
+) -> String {
+    // Vertex inputs and fragment outputs only have to agree with the host
+    let own = |output: bool| match shader_type {
+        ShaderType::Vertex => !output,
+        ShaderType::Fragment => output,
+        _ => false,
+    };
+
+    let mut next = [0, 0];
+    for line in text.lines() {
+        let (location, variable) = match split_layout(line.trim_start()) {
+            Some((qualifiers, rest)) => {
+                match (
+                    layout_value(qualifiers, "location"),
+                    interface_variable(rest),
+                ) {
+                    (Some(location), Some(variable)) => (location, variable),
+                    _ => continue,
+                }
+            }
+            None => continue,
+        };
+        let end = location + variable.locations();
+        if own(variable.output) {
+            let next = &mut next[variable.output as usize];
+            *next = (*next).max(end);
+        } else {
+            layouts.varyings.insert(variable.name.to_string(), location);
+            layouts.next_varying = layouts.next_varying.max(end);
+        }
+    }
+
+    let mut matrices = vec![];
+    let mut out = String::with_capacity(text.len());
+    for line in text.lines() {
+        let declaration = line.trim_start();
+        let indent = &line[..line.len() - declaration.len()];
+
+        let rewritten = match split_layout(declaration) {
+            Some((qualifiers, rest)) if rest.starts_with("uniform ") => {
+                if has_layout_key(qualifiers, "binding") {
+                    None
+                } else {
+                    let key = rest
+                        .trim_end_matches(|c: char| c == ';' || c == '{' || c.is_whitespace())
+                        .to_string();
+                    let next = layouts.bindings.len() as u32;
+                    let binding = *layouts.bindings.entry(key).or_insert(next);
+                    Some(with_layout(
+                        &format!("binding = {}", binding),
+                        qualifiers,
+                        rest,
+                    ))
+                }
+            }
+            Some((qualifiers, rest)) => interface_variable(rest).and_then(|variable| {
+                let explicit = layout_value(qualifiers, "location");
+                let location = match explicit {
+                    Some(location) => location,
+                    None if own(variable.output) => {
+                        let next = &mut next[variable.output as usize];
+                        *next += variable.locations();
+                        *next - variable.locations()
+                    }
+                    None => match layouts.varyings.get(variable.name) {
+                        Some(location) => *location,
+                        None => {
+                            let location = layouts.next_varying;
+                            layouts.next_varying += variable.locations();
+                            layouts.varyings.insert(variable.name.to_string(), location);
+                            location
+                        }
+                    },
+                };
+
+                match variable.matrix() {
+                    Some((columns, rows))
+                        if shader_type == ShaderType::Vertex && !variable.output =>
+                    {
+                        let names: Vec<String> = (0..columns)
+                            .map(|column| format!("{}_column{}", variable.name, column))
+                            .collect();
+                        matrices.push((
+                            variable.name,
+                            format!("{}({})", variable.gl_type, names.join(", ")),
+                        ));
+                        let declarations: Vec<String> = names
+                            .iter()
+                            .zip(location..)
+                            .map(|(name, location)| {
+                                format!("layout(location = {}) in vec{} {};", location, rows, name)
+                            })
+                            .collect();
+                        Some(declarations.join(" "))
+                    }
+                    _ if explicit.is_some() => None,
+                    _ => Some(with_layout(
+                        &format!("location = {}", location),
+                        qualifiers,
+                        rest,
+                    )),
+                }
+            }),
+            None => None,
+        };
+
+        match rewritten {
+            Some(rewritten) => {
+                out += indent;
+                out += &rewritten;
+            }
+            None => out += line,
+        }
+        out += "\n";
+    }
+
+    for (name, columns) in matrices {
+        out = replace_identifier(&out, name, &columns);
+    }
+    out
+}
+
+/// Parses `text`, the naga version of `stage`, and validates the module.
+/// Problems are added to `diagnostics`, including the ones that only
+/// matter to Vulkan when `vulkan` is set; `None` means the stage has errors
+/// or naga cannot parse its kind.
+pub(super) fn parse_stage(
+    stage: &Shader,
+    text: &str,
+    source_path: &Path,
+    vulkan: bool,
+    diagnostics: &mut Vec<ShaderDiagnostic>,
+) -> Option<(Module, ModuleInfo)> {
+    let naga_stage = match naga_stage(stage.shader_type) {
+        Some(naga_stage) => naga_stage,
+        None => {
+            diagnostics.push(ShaderDiagnostic {
+                path: source_path.to_path_buf(),
+                line: None,
+                severity: Severity::Note,
+                message: format!("{:?} stage not checked", stage.shader_type),
+            });
+            return None;
+        }
+    };
+
+    match Parser::default().parse(&Options::from(naga_stage), text) {
+        Ok(module) => {
+            let validation =
+                Validator::new(ValidationFlags::all(), Capabilities::all()).validate(&module);
+            match validation {
+                Ok(info) => Some((module, info)),
+                Err(error) => {
+                    // The top-level error only names the entry point
+                    let mut message = format!("{:?} stage: {}", stage.shader_type, error);
+                    let mut cause = std::error::Error::source(&error);
+                    while let Some(error) = cause {
+                        message += &format!(": {}", error);
+                        cause = error.source();
+                    }
+                    diagnostics.push(ShaderDiagnostic {
+                        path: source_path.to_path_buf(),
+                        line: None,
+                        severity: Severity::Error,
+                        message,
+                    });
+                    None
+                }
+            }
+        }
+        Err(errors) => {
+            for error in errors
+                .iter()
+                .filter(|error| vulkan || !vulkan_only(&error.kind))
+            {
+                let line = error.meta.location(text).line_number as usize;
+                let (path, line) = match stage.line_map.resolve(line) {
+                    Some((path, line)) => (path.to_path_buf(), Some(line)),
+                    None => (source_path.to_path_buf(), None),
+                };
+                let severity = match error.kind {
+                    ErrorKind::NotImplemented(_) => Severity::Warning,
+                    _ => Severity::Error,
+                };
+                diagnostics.push(ShaderDiagnostic {
+                    path,
+                    line,
+                    severity,
+                    message: error.to_string(),
+                });
+            }
+            None
+        }
+    }
+}
+
+/// Checks every stage of a `.shader` file with naga's GLSL front end, so
+/// errors can be found without a GL context.
+///
+/// Splitting errors (a missing include, an unknown stage) are returned as
+/// `Err`. Everything naga reports is returned as diagnostics pointing into
+/// the `.shader` file or its includes. Stages naga cannot parse, such as
+/// geometry and tessellation, are skipped with a note.
+pub fn validate(source: impl Into<ShaderSource>) -> Result<Vec<ShaderDiagnostic>, ShaderError> {
+    let shader_data = ShaderData::new(source)?;
+    let source_path = Path::new(&shader_data.source_path);
+    let mut layouts = Layouts::default();
+    let mut diagnostics = vec![];
+
+    for stage in shader_data.stages.iter() {
+        let text = assign_bindings(&naga_source(stage), stage.shader_type, &mut layouts);
+        parse_stage(stage, &text, source_path, false, &mut diagnostics);
+    }
+
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assign_bindings, validate, Layouts};
+    use crate::shaders::diagnostic::{Severity, ShaderDiagnostic};
+    use crate::shaders::{ShaderSource, ShaderType};
+
+    fn validate_vertex(body: &str) -> Vec<ShaderDiagnostic> {
+        let source = format!("#shader version 330 core\n#shader vertex\n{}", body);
+        validate(ShaderSource::owned("vertex.shader", source)).unwrap()
+    }
+
+    #[test]
+    fn accepts_inputs_without_locations() {
+        let diagnostics = validate_vertex(
+            "uniform mat4 u_model;\n\
+             in vec2 in_position;\n\
+             in vec2 in_uv;\n\
+             in mat4 in_instance_model;\n\
+             out vec2 uv;\n\
+             void main() {\n\
+             \x20 uv = in_uv;\n\
+             \x20 gl_Position = u_model*in_instance_model*vec4(in_position, 0.0, 1.0);\n\
+             }\n",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn runs_the_validator_on_plain_uniforms() {
+        // Parses fine, but only the validator knows uniforms are read-only
+        let diagnostics = validate_vertex(
+            "uniform mat4 u_model;\n\
+             in vec2 in_position;\n\
+             void main() {\n\
+             \x20 u_model = mat4(1.0);\n\
+             \x20 gl_Position = u_model*vec4(in_position, 0.0, 1.0);\n\
+             }\n",
+        );
+
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert!(
+            diagnostics[0].message.contains("store"),
+            "{}",
+            diagnostics[0]
+        );
+    }
+
+    #[test]
+    fn accepts_the_shaders_in_resources() {
+        for path in [
+            "resources/base_shader.shader",
+            "resources/instanced.shader",
+            "resources/shader_with_matrix.shader",
+        ] {
+            let diagnostics = validate(path).unwrap();
+            assert!(diagnostics.is_empty(), "{}: {:?}", path, diagnostics);
+        }
+    }
+
+    #[test]
+    fn assigns_bindings_to_plain_uniforms() {
+        let mut layouts = Layouts::default();
+        let vertex = assign_bindings(
+            "uniform mat4 u_model;\n  uniform float blue;\n",
+            ShaderType::Vertex,
+            &mut layouts,
+        );
+        let fragment = assign_bindings("uniform float blue;\n", ShaderType::Fragment, &mut layouts);
+
+        assert_eq!(
+            vertex,
+            "layout(binding = 0) uniform mat4 u_model;\n  layout(binding = 1) uniform float blue;\n"
+        );
+        assert_eq!(fragment, "layout(binding = 1) uniform float blue;\n");
+    }
+
+    #[test]
+    fn merges_bindings_into_existing_layouts() {
+        let mut layouts = Layouts::default();
+        let text = "layout(std140) uniform Camera {\n\
+                    layout( std140, binding = 3 ) uniform Lights {\n\
+                    layout(location = 0) in vec2 in_position;\n";
+
+        assert_eq!(
+            assign_bindings(text, ShaderType::Vertex, &mut layouts),
+            "layout(binding = 0, std140) uniform Camera {\n\
+             layout( std140, binding = 3 ) uniform Lights {\n\
+             layout(location = 0) in vec2 in_position;\n"
+        );
+    }
+
+    #[test]
+    fn numbers_vertex_inputs_after_explicit_locations() {
+        let mut layouts = Layouts::default();
+        let text = "in vec2 in_uv;\n\
+                    layout(location = 1) in vec2 in_position;\n\
+                    in vec4 in_colors[2];\n\
+                    void main(in float x) {}\n";
+
+        assert_eq!(
+            assign_bindings(text, ShaderType::Vertex, &mut layouts),
+            "layout(location = 2) in vec2 in_uv;\n\
+             layout(location = 1) in vec2 in_position;\n\
+             layout(location = 3) in vec4 in_colors[2];\n\
+             void main(in float x) {}\n"
+        );
+    }
+
+    #[test]
+    fn reads_matrix_inputs_column_by_column() {
+        let mut layouts = Layouts::default();
+        let text = "in mat3x2 in_model;\n\
+                    in vec2 in_position;\n\
+                    vec2 in_model_position = in_model*vec3(in_position, 1.0);\n";
+
+        assert_eq!(
+            assign_bindings(text, ShaderType::Vertex, &mut layouts),
+            "layout(location = 0) in vec2 in_model_column0; \
+             layout(location = 1) in vec2 in_model_column1; \
+             layout(location = 2) in vec2 in_model_column2;\n\
+             layout(location = 3) in vec2 in_position;\n\
+             vec2 in_model_position = \
+             mat3x2(in_model_column0, in_model_column1, in_model_column2)*vec3(in_position, 1.0);\n"
+        );
+    }
+
+    #[test]
+    fn shares_locations_between_outputs_and_inputs_by_name() {
+        let mut layouts = Layouts::default();
+        let vertex = assign_bindings(
+            "in vec2 in_position;\nout vec2 uv;\nflat out vec4 tint;\n",
+            ShaderType::Vertex,
+            &mut layouts,
+        );
+        let fragment = assign_bindings(
+            "flat in vec4 tint;\nin vec2 uv;\nout vec4 color;\n",
+            ShaderType::Fragment,
+            &mut layouts,
+        );
+
+        assert_eq!(
+            vertex,
+            "layout(location = 0) in vec2 in_position;\n\
+             layout(location = 0) out vec2 uv;\n\
+             layout(location = 1) flat out vec4 tint;\n"
+        );
+        assert_eq!(
+            fragment,
+            "layout(location = 1) flat in vec4 tint;\n\
+             layout(location = 0) in vec2 uv;\n\
+             layout(location = 0) out vec4 color;\n"
+        );
+    }
+}
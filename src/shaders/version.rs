@@ -0,0 +1,242 @@
+use std::fmt;
+
+use glow::HasContext;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GlslProfile {
+    Core,
+    Compatibility,
+    Es,
+}
+
+/// The `#version` a stage is emitted with, e.g. `330 core` or `300 es`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GlslVersion {
+    pub number: u32,
+    pub profile: GlslProfile,
+}
+
+impl fmt::Display for GlslVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.profile {
+            // Profiles only exist from GLSL 1.50 on.
+            GlslProfile::Core | GlslProfile::Compatibility if self.number < 150 => {
+                write!(f, "{}", self.number)
+            }
+            GlslProfile::Core => write!(f, "{} core", self.number),
+            GlslProfile::Compatibility => write!(f, "{} compatibility", self.number),
+            // GLSL ES 1.00 predates the `es` suffix.
+            GlslProfile::Es if self.number == 100 => write!(f, "{}", self.number),
+            GlslProfile::Es => write!(f, "{} es", self.number),
+        }
+    }
+}
+
+impl GlslVersion {
+    pub fn new(number: u32, profile: GlslProfile) -> Self {
+        Self { number, profile }
+    }
+
+    /// The version used when a `.shader` file has no `#shader version` line.
+    pub fn platform_default() -> Self {
+        #[cfg(target_os = "macos")]
+        return GlslVersion::new(410, GlslProfile::Core);
+        #[cfg(not(target_os = "macos"))]
+        return GlslVersion::new(130, GlslProfile::Core);
+    }
+
+    /// Parses the text following `#shader version`, such as `330 core`,
+    /// `300 es` or a bare `130`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut words = text.split_whitespace();
+        let number: u32 = words.next()?.parse().ok()?;
+        let profile = match words.next() {
+            None if number == 100 => GlslProfile::Es,
+            None | Some("core") => GlslProfile::Core,
+            Some("compatibility") => GlslProfile::Compatibility,
+            Some("es") => GlslProfile::Es,
+            Some(_) => return None,
+        };
+        if words.next().is_some() {
+            return None;
+        }
+        Some(GlslVersion::new(number, profile))
+    }
+
+    pub fn directive(&self) -> String {
+        format!("#version {}", self)
+    }
+
+    /// Converts to the closest version of another profile, so a file written
+    /// for `330 core` can be emitted as `300 es` and the other way round.
+    pub fn with_profile(self, profile: GlslProfile) -> Self {
+        let number = match (self.profile, profile) {
+            (GlslProfile::Es, GlslProfile::Es) => self.number,
+            (_, GlslProfile::Es) => match self.number {
+                0..=330 => 300,
+                331..=430 => 310,
+                _ => 320,
+            },
+            (GlslProfile::Es, _) => match self.number {
+                0..=100 => 150,
+                101..=300 => 330,
+                301..=310 => 430,
+                _ => 450,
+            },
+            // Core profiles only exist from GLSL 1.50 on
+            (GlslProfile::Compatibility, GlslProfile::Core) => self.number.max(150),
+            _ => self.number,
+        };
+        GlslVersion::new(number, profile)
+    }
+
+    /// The newest version a context can compile.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn supported_by(gl: &glow::Context) -> Self {
+        let version = gl.version();
+        if version.is_embedded {
+            let number = match (version.major, version.minor) {
+                (3, minor) => 300 + minor * 10,
+                (major, _) if major > 3 => 320,
+                _ => 100,
+            };
+            return GlslVersion::new(number, GlslProfile::Es);
+        }
+
+        let number = match (version.major, version.minor) {
+            (2, 0) => 110,
+            (2, _) => 120,
+            (3, 0) => 130,
+            (3, 1) => 140,
+            (3, 2) => 150,
+            (major, minor) => major * 100 + minor * 10,
+        };
+
+        let core = (version.major, version.minor) >= (3, 2)
+            && gl.get_parameter_i32(glow::CONTEXT_PROFILE_MASK) as u32
+                & glow::CONTEXT_CORE_PROFILE_BIT
+                != 0;
+        let profile = if core {
+            GlslProfile::Core
+        } else {
+            GlslProfile::Compatibility
+        };
+
+        GlslVersion::new(number, profile)
+    }
+
+    /// Whether a context supporting up to `supported` can compile `self`.
+    pub fn fits(&self, supported: &GlslVersion) -> bool {
+        let same_family =
+            (self.profile == GlslProfile::Es) == (supported.profile == GlslProfile::Es);
+        let profile_ok = !(self.profile == GlslProfile::Compatibility
+            && self.number >= 150
+            && supported.profile == GlslProfile::Core);
+
+        same_family && profile_ok && self.number <= supported.number
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GlslProfile, GlslVersion};
+
+    fn version(number: u32, profile: GlslProfile) -> GlslVersion {
+        GlslVersion::new(number, profile)
+    }
+
+    #[test]
+    fn parses_versions() {
+        use GlslProfile::*;
+
+        assert_eq!(GlslVersion::parse("330 core"), Some(version(330, Core)));
+        assert_eq!(GlslVersion::parse(" 130 "), Some(version(130, Core)));
+        assert_eq!(
+            GlslVersion::parse("450 compatibility"),
+            Some(version(450, Compatibility))
+        );
+        assert_eq!(GlslVersion::parse("300 es"), Some(version(300, Es)));
+        assert_eq!(GlslVersion::parse("100"), Some(version(100, Es)));
+
+        assert_eq!(GlslVersion::parse(""), None);
+        assert_eq!(GlslVersion::parse("core"), None);
+        assert_eq!(GlslVersion::parse("330 desktop"), None);
+        assert_eq!(GlslVersion::parse("330 core extra"), None);
+    }
+
+    #[test]
+    fn displays_profiles_from_150_on() {
+        use GlslProfile::*;
+
+        assert_eq!(version(130, Core).directive(), "#version 130");
+        assert_eq!(version(130, Compatibility).directive(), "#version 130");
+        assert_eq!(version(330, Core).directive(), "#version 330 core");
+        assert_eq!(
+            version(150, Compatibility).directive(),
+            "#version 150 compatibility"
+        );
+        assert_eq!(version(100, Es).directive(), "#version 100");
+        assert_eq!(version(300, Es).directive(), "#version 300 es");
+    }
+
+    #[test]
+    fn keeps_version_within_a_profile() {
+        use GlslProfile::*;
+
+        assert_eq!(version(130, Core).with_profile(Core), version(130, Core));
+        assert_eq!(version(300, Es).with_profile(Es), version(300, Es));
+        assert_eq!(
+            version(130, Compatibility).with_profile(Compatibility),
+            version(130, Compatibility)
+        );
+        assert_eq!(
+            version(330, Core).with_profile(Compatibility),
+            version(330, Compatibility)
+        );
+    }
+
+    #[test]
+    fn converts_between_profiles() {
+        use GlslProfile::*;
+
+        assert_eq!(
+            version(130, Compatibility).with_profile(Core),
+            version(150, Core)
+        );
+        assert_eq!(
+            version(330, Compatibility).with_profile(Core),
+            version(330, Core)
+        );
+
+        assert_eq!(version(130, Core).with_profile(Es), version(300, Es));
+        assert_eq!(version(410, Core).with_profile(Es), version(310, Es));
+        assert_eq!(version(460, Core).with_profile(Es), version(320, Es));
+
+        assert_eq!(version(100, Es).with_profile(Core), version(150, Core));
+        assert_eq!(version(300, Es).with_profile(Core), version(330, Core));
+        assert_eq!(version(310, Es).with_profile(Core), version(430, Core));
+        assert_eq!(version(320, Es).with_profile(Core), version(450, Core));
+    }
+
+    #[test]
+    fn fits_supported_versions() {
+        use GlslProfile::*;
+
+        let core = version(410, Core);
+        assert!(version(330, Core).fits(&core));
+        assert!(version(410, Core).fits(&core));
+        assert!(!version(430, Core).fits(&core));
+        // Compatibility below 1.50 is plain GLSL, anything else is not core
+        assert!(version(130, Compatibility).fits(&core));
+        assert!(!version(330, Compatibility).fits(&core));
+        assert!(version(330, Core).fits(&version(450, Compatibility)));
+
+        let es = version(300, Es);
+        assert!(version(100, Es).fits(&es));
+        assert!(!version(310, Es).fits(&es));
+        assert!(!version(130, Core).fits(&es));
+        assert!(!version(300, Es).fits(&core));
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use super::ShaderData;
+
+/// A watched `.shader` file, with every set of defines it is compiled with
+/// and the files those permutations depend on.
+#[derive(Default)]
+struct Watched {
+    permutations: Vec<Vec<(String, String)>>,
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+/// Polls `.shader` files and their includes for changes between frames.
+#[derive(Default)]
+pub struct ShaderWatcher {
+    watched: HashMap<String, Watched>,
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+impl ShaderWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts watching `source_path` compiled with `defines`, and the files
+    /// it includes with them. Watching a permutation twice is a no-op.
+    pub fn watch(&mut self, source_path: &str, defines: &[(String, String)]) {
+        let watched = self.watched.entry(source_path.to_string()).or_default();
+        if !watched.permutations.iter().any(|known| known == defines) {
+            watched.permutations.push(defines.to_vec());
+            let previous = watched.files.iter().map(|(path, _)| path.clone()).collect();
+            watched.files = Self::dependencies(source_path, &watched.permutations, previous);
+        }
+    }
+
+    pub fn unwatch(&mut self, source_path: &str) {
+        self.watched.remove(source_path);
+    }
+
+    /// Stops watching the files `keep` returns false for, e.g. once their
+    /// programs were released.
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        self.watched.retain(|source_path, _| keep(source_path));
+    }
+
+    /// Returns the watched `.shader` files whose own source or includes
+    /// changed since the last poll.
+    pub fn poll(&mut self) -> Vec<String> {
+        let mut changed = vec![];
+
+        for (source_path, watched) in self.watched.iter_mut() {
+            let dirty = watched
+                .files
+                .iter()
+                .any(|(path, last_modified)| modified(path) != *last_modified);
+
+            if dirty {
+                // Includes may have been added or removed by the edit.
+                let previous = watched.files.iter().map(|(path, _)| path.clone()).collect();
+                watched.files = Self::dependencies(source_path, &watched.permutations, previous);
+                changed.push(source_path.clone());
+            }
+        }
+
+        changed.sort();
+        changed
+    }
+
+    /// Lists the files `source_path` depends on with any of `permutations`
+    /// and their current modification times, falling back to `previous`
+    /// while the file does not parse.
+    fn dependencies(
+        source_path: &str,
+        permutations: &[Vec<(String, String)>],
+        previous: Vec<PathBuf>,
+    ) -> Vec<(PathBuf, Option<SystemTime>)> {
+        let mut files: Vec<PathBuf> = vec![];
+        for defines in permutations {
+            let shader_data = ShaderData::builder(source_path.to_string())
+                .defines(defines)
+                .build();
+            let dependencies = match shader_data {
+                Ok(shader_data) => shader_data.dependencies,
+                Err(_) => continue,
+            };
+            for path in dependencies {
+                if !files.contains(&path) {
+                    files.push(path);
+                }
+            }
+        }
+
+        if files.is_empty() {
+            files = if previous.is_empty() {
+                vec![PathBuf::from(source_path)]
+            } else {
+                previous
+            };
+        }
+
+        files
+            .into_iter()
+            .map(|path| {
+                let last_modified = modified(&path);
+                (path, last_modified)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+
+    use super::ShaderWatcher;
+
+    /// Writes `files` to a fresh directory named after the test.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("gl_object_watcher_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (name, source) in files {
+            fs::write(dir.join(name), source).unwrap();
+        }
+        dir
+    }
+
+    /// Moves the modification time of `path` `minutes` ahead, as an edit
+    /// would.
+    fn touch(path: &Path, minutes: u64) {
+        let later = SystemTime::now() + Duration::from_secs(60 * minutes);
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+    }
+
+    fn defines(names: &[&str]) -> Vec<(String, String)> {
+        names
+            .iter()
+            .map(|name| (name.to_string(), String::new()))
+            .collect()
+    }
+
+    #[test]
+    fn reports_files_whose_modification_time_changed() {
+        let dir = write_files(
+            "mtime",
+            &[
+                ("a.shader", "#shader vertex\n#include \"b.glsl\"\n"),
+                ("b.glsl", "float b;\n"),
+            ],
+        );
+        let source = dir.join("a.shader").to_str().unwrap().to_string();
+        let mut watcher = ShaderWatcher::new();
+        watcher.watch(&source, &[]);
+        assert!(watcher.poll().is_empty());
+
+        touch(&dir.join("b.glsl"), 1);
+        assert_eq!(watcher.poll(), std::slice::from_ref(&source));
+        assert!(watcher.poll().is_empty());
+
+        touch(&dir.join("a.shader"), 2);
+        assert_eq!(watcher.poll(), [source]);
+    }
+
+    #[test]
+    fn follows_includes_of_every_permutation() {
+        let dir = write_files(
+            "defines",
+            &[
+                (
+                    "a.shader",
+                    "#shader vertex\n#ifdef LIT\n#include \"light.glsl\"\n#endif\n",
+                ),
+                ("light.glsl", "float light;\n"),
+            ],
+        );
+        let source = dir.join("a.shader").to_str().unwrap().to_string();
+        let mut watcher = ShaderWatcher::new();
+        watcher.watch(&source, &[]);
+
+        touch(&dir.join("light.glsl"), 3);
+        assert!(watcher.poll().is_empty());
+
+        watcher.watch(&source, &defines(&["LIT"]));
+        touch(&dir.join("light.glsl"), 4);
+        assert_eq!(watcher.poll(), [source]);
+    }
+
+    #[test]
+    fn forgets_files_it_no_longer_keeps() {
+        let dir = write_files("retain", &[("a.shader", "#shader vertex\n")]);
+        let source = dir.join("a.shader").to_str().unwrap().to_string();
+        let mut watcher = ShaderWatcher::new();
+        watcher.watch(&source, &[]);
+
+        watcher.retain(|path| path != source);
+        touch(&dir.join("a.shader"), 5);
+        assert!(watcher.poll().is_empty());
+    }
+}
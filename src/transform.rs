@@ -0,0 +1,135 @@
+use gfx_maths::{Mat4, Quaternion, Vec3};
+
+/// Where an object is placed in the world: scaled, then rotated, around
+/// `pivot`, then moved by `translation`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quaternion,
+    /// Scale along each axis of the object, applied before the rotation.
+    pub scale: Vec3,
+    /// Point, in the coordinates of the object, that stays in place when it
+    /// is rotated or scaled, e.g. the center of a rectangle instead of its
+    /// top left corner.
+    pub pivot: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transform {
+    /// A transform that leaves the object as it is.
+    pub const fn new() -> Self {
+        Self {
+            translation: Vec3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::identity(),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+            pivot: Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self {
+            translation,
+            ..Self::new()
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: Quaternion) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_pivot(mut self, pivot: Vec3) -> Self {
+        self.pivot = pivot;
+        self
+    }
+
+    /// Moves the object by `offset`.
+    pub fn translate(&mut self, offset: Vec3) {
+        self.translation += offset;
+    }
+
+    /// Rotates the object by `rotation` on top of its current rotation.
+    pub fn rotate(&mut self, rotation: Quaternion) {
+        self.rotation = rotation * self.rotation;
+    }
+
+    /// Rotates the object by `radians` around the Z axis, the rotation 2D
+    /// objects use.
+    pub fn rotate_z(&mut self, radians: f32) {
+        self.rotate(Quaternion::axis_angle(Vec3::new(0.0, 0.0, 1.0), radians));
+    }
+
+    /// The model matrix, from object to world coordinates.
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::translate(self.translation + self.pivot)
+            * Mat4::rotate(self.rotation)
+            * Mat4::scale(self.scale)
+            * Mat4::translate(-self.pivot)
+    }
+
+    /// Maps a point in world coordinates back into the coordinates of the
+    /// object, e.g. to hit test it. Axes scaled to zero map to infinity.
+    pub fn to_local(&self, point: Vec3) -> Vec3 {
+        let rotated = -self.rotation * (point - self.translation - self.pivot);
+        rotated / self.scale + self.pivot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gfx_maths::{Mat4, Quaternion, Vec3, Vec4};
+
+    use super::Transform;
+
+    /// `matrix * point`, with the translation `Mat4 * Vec3` leaves out.
+    fn apply(matrix: Mat4, point: Vec3) -> Vec3 {
+        let point = matrix * Vec4::new(point.x, point.y, point.z, 1.0);
+        Vec3::new(point.x, point.y, point.z)
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn maps_world_points_back_to_local() {
+        let transform = Transform::from_translation(Vec3::new(3.0, -2.0, 1.0))
+            .with_rotation(Quaternion::axis_angle(Vec3::new(0.0, 0.0, 1.0), 0.7))
+            .with_scale(Vec3::new(2.0, 0.5, 1.0))
+            .with_pivot(Vec3::new(4.0, 4.0, 0.0));
+
+        for point in [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(4.0, 4.0, 0.0),
+            Vec3::new(-5.0, 8.0, 2.0),
+        ] {
+            assert_close(transform.to_local(apply(transform.matrix(), point)), point);
+        }
+    }
+
+    #[test]
+    fn keeps_the_pivot_in_place() {
+        let pivot = Vec3::new(5.0, 5.0, 0.0);
+        let mut transform = Transform::new()
+            .with_scale(Vec3::new(3.0, 3.0, 1.0))
+            .with_pivot(pivot);
+        transform.rotate_z(1.2);
+
+        assert_close(apply(transform.matrix(), pivot), pivot);
+        assert_close(transform.to_local(pivot), pivot);
+        assert_close(
+            apply(transform.matrix(), Vec3::new(6.0, 5.0, 0.0)),
+            pivot + Vec3::new(3.0 * 1.2f32.cos(), 3.0 * 1.2f32.sin(), 0.0),
+        );
+    }
+}
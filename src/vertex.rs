@@ -0,0 +1,455 @@
+use gfx_maths::{Mat4, Vec2, Vec3, Vec4};
+use glow::{Context, HasContext};
+
+use crate::program::reflection::{matrix_columns, ProgramReflection, VertexInputMismatch};
+use crate::resource::Buffer;
+
+pub use gl_object_derive::Vertex;
+
+/// Scalar type of the components of a vertex attribute in a buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttributeType {
+    F32,
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+}
+
+impl AttributeType {
+    /// The `glow::*` constant passed to `glVertexAttribPointer`.
+    pub fn gl_type(self) -> u32 {
+        match self {
+            AttributeType::F32 => glow::FLOAT,
+            AttributeType::I8 => glow::BYTE,
+            AttributeType::U8 => glow::UNSIGNED_BYTE,
+            AttributeType::I16 => glow::SHORT,
+            AttributeType::U16 => glow::UNSIGNED_SHORT,
+            AttributeType::I32 => glow::INT,
+            AttributeType::U32 => glow::UNSIGNED_INT,
+        }
+    }
+
+    /// Size of one component in bytes.
+    pub fn size(self) -> i32 {
+        match self {
+            AttributeType::I8 | AttributeType::U8 => 1,
+            AttributeType::I16 | AttributeType::U16 => 2,
+            AttributeType::F32 | AttributeType::I32 | AttributeType::U32 => 4,
+        }
+    }
+}
+
+/// One named input of the vertex shader, as it is stored in a buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VertexAttribute {
+    /// Name of the `in` variable of the vertex stage.
+    pub name: String,
+    /// Number of components, 1 to 4, or all the components of a matrix
+    /// input, e.g. 16 for a `mat4`.
+    pub components: i32,
+    pub attribute_type: AttributeType,
+    /// Whether integers are mapped to `[0, 1]` or `[-1, 1]` for float inputs.
+    pub normalized: bool,
+    /// Byte offset from the start of a vertex.
+    pub offset: i32,
+}
+
+impl VertexAttribute {
+    pub fn new(name: &str, components: i32, attribute_type: AttributeType) -> Self {
+        Self {
+            name: name.to_string(),
+            components,
+            attribute_type,
+            normalized: false,
+            offset: 0,
+        }
+    }
+
+    /// An attribute of `components` floats.
+    pub fn f32(name: &str, components: i32) -> Self {
+        Self::new(name, components, AttributeType::F32)
+    }
+
+    pub fn normalized(mut self) -> Self {
+        self.normalized = true;
+        self
+    }
+
+    /// Places the attribute at `offset` bytes into the vertex instead of
+    /// after the previous attribute.
+    pub fn offset(mut self, offset: i32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Size of the attribute in bytes.
+    pub fn size(&self) -> i32 {
+        self.components * self.attribute_type.size()
+    }
+}
+
+/// The attributes read from one vertex buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VertexBufferLayout {
+    /// Bytes from one vertex to the next.
+    pub stride: i32,
+    pub attributes: Vec<VertexAttribute>,
+    /// 0 to advance every vertex, or the number of instances drawn before
+    /// advancing, for per-instance data.
+    pub divisor: u32,
+}
+
+impl VertexBufferLayout {
+    /// Reads one element per instance instead of one per vertex.
+    pub fn instanced(mut self) -> Self {
+        self.divisor = 1;
+        self
+    }
+
+    /// Places `attributes` one after the other, with no padding in between.
+    pub fn packed(attributes: Vec<VertexAttribute>) -> Self {
+        let mut stride = 0;
+        let attributes = attributes
+            .into_iter()
+            .map(|attribute| {
+                let attribute = attribute.offset(stride);
+                stride += attribute.size();
+                attribute
+            })
+            .collect();
+        Self {
+            stride,
+            attributes,
+            divisor: 0,
+        }
+    }
+}
+
+/// How vertices are laid out in one or more buffers, see
+/// `OpenGLObjectTrait::setup_buffers`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VertexLayout {
+    pub buffers: Vec<VertexBufferLayout>,
+}
+
+/// Whether the vertex shader reads `gl_type` as integers, which needs
+/// `glVertexAttribIPointer`.
+fn is_integer_input(gl_type: u32) -> bool {
+    matches!(
+        gl_type,
+        glow::INT
+            | glow::INT_VEC2
+            | glow::INT_VEC3
+            | glow::INT_VEC4
+            | glow::UNSIGNED_INT
+            | glow::UNSIGNED_INT_VEC2
+            | glow::UNSIGNED_INT_VEC3
+            | glow::UNSIGNED_INT_VEC4
+    )
+}
+
+impl VertexLayout {
+    pub fn new(buffers: Vec<VertexBufferLayout>) -> Self {
+        Self { buffers }
+    }
+
+    /// Every attribute in one buffer, one vertex after the other.
+    pub fn interleaved(attributes: Vec<VertexAttribute>) -> Self {
+        Self::new(vec![VertexBufferLayout::packed(attributes)])
+    }
+
+    /// Every attribute in a buffer of its own.
+    pub fn split(attributes: Vec<VertexAttribute>) -> Self {
+        Self::new(
+            attributes
+                .into_iter()
+                .map(|attribute| VertexBufferLayout::packed(vec![attribute]))
+                .collect(),
+        )
+    }
+
+    pub fn attributes(&self) -> impl Iterator<Item = &VertexAttribute> {
+        self.buffers
+            .iter()
+            .flat_map(|buffer| buffer.attributes.iter())
+    }
+
+    /// Compares the attributes with the vertex inputs of a program.
+    pub fn check(&self, reflection: &ProgramReflection) -> Vec<VertexInputMismatch> {
+        let provided: Vec<(&str, i32)> = self
+            .attributes()
+            .map(|attribute| (attribute.name.as_str(), attribute.components))
+            .collect();
+        reflection.check_vertex_inputs(&provided)
+    }
+
+    /// Points every attribute the program reads at its buffer in
+    /// `buffers`, which holds one buffer per entry of `self.buffers`, using
+    /// the locations in `reflection`. Attributes the program does not read
+    /// are skipped, and matrix inputs take one location per column. The
+    /// vertex array to record into must be bound.
+    ///
+    /// # Safety
+    /// `gl` must be current on this thread.
+    pub unsafe fn bind(&self, gl: &Context, buffers: &[Buffer], reflection: &ProgramReflection) {
+        assert_eq!(
+            buffers.len(),
+            self.buffers.len(),
+            "one buffer is needed per buffer layout"
+        );
+
+        for (layout, buffer) in self.buffers.iter().zip(buffers) {
+            buffer.bind(gl);
+            for attribute in layout.attributes.iter() {
+                let input = match reflection.attribute(&attribute.name) {
+                    Some(input) => input,
+                    None => continue,
+                };
+                let location = match input.location {
+                    Some(location) => location,
+                    None => continue,
+                };
+
+                let data_type = attribute.attribute_type.gl_type();
+                let columns = matrix_columns(input.gl_type);
+                let components = attribute.components / columns;
+                for column in 0..columns {
+                    let location = location + column as u32;
+                    let offset =
+                        attribute.offset + column * components * attribute.attribute_type.size();
+
+                    gl.enable_vertex_attrib_array(location);
+                    gl.vertex_attrib_divisor(location, layout.divisor);
+                    if is_integer_input(input.gl_type)
+                        && attribute.attribute_type != AttributeType::F32
+                    {
+                        gl.vertex_attrib_pointer_i32(
+                            location,
+                            components,
+                            data_type,
+                            layout.stride,
+                            offset,
+                        );
+                    } else {
+                        gl.vertex_attrib_pointer_f32(
+                            location,
+                            components,
+                            data_type,
+                            attribute.normalized,
+                            layout.stride,
+                            offset,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A Rust type a vertex field can have, and how GL reads it.
+///
+/// # Safety
+/// The type must be `COMPONENTS` values of `TYPE` with no padding.
+pub unsafe trait AttributeFormat: Copy {
+    const COMPONENTS: i32;
+    const TYPE: AttributeType;
+}
+
+macro_rules! attribute_format {
+    ($($rust:ty => $attribute_type:ident),*) => {
+        $(
+            unsafe impl AttributeFormat for $rust {
+                const COMPONENTS: i32 = 1;
+                const TYPE: AttributeType = AttributeType::$attribute_type;
+            }
+
+            unsafe impl<const N: usize> AttributeFormat for [$rust; N] {
+                const COMPONENTS: i32 = N as i32;
+                const TYPE: AttributeType = AttributeType::$attribute_type;
+            }
+        )*
+    };
+}
+
+attribute_format!(f32 => F32, i8 => I8, u8 => U8, i16 => I16, u16 => U16, i32 => I32, u32 => U32);
+
+unsafe impl AttributeFormat for Vec2 {
+    const COMPONENTS: i32 = 2;
+    const TYPE: AttributeType = AttributeType::F32;
+}
+
+unsafe impl AttributeFormat for Vec3 {
+    const COMPONENTS: i32 = 3;
+    const TYPE: AttributeType = AttributeType::F32;
+}
+
+unsafe impl AttributeFormat for Vec4 {
+    const COMPONENTS: i32 = 4;
+    const TYPE: AttributeType = AttributeType::F32;
+}
+
+/// Feeds a `mat4` input, one column per location.
+unsafe impl AttributeFormat for Mat4 {
+    const COMPONENTS: i32 = 16;
+    const TYPE: AttributeType = AttributeType::F32;
+}
+
+/// A vertex struct that can be uploaded as is, usually implemented with
+/// `#[derive(Vertex)]`:
+///
+/// ```
+/// use gl_object::vertex::Vertex;
+///
+/// #[repr(C)]
+/// #[derive(Clone, Copy, Vertex)]
+/// struct ColoredVertex {
+///     in_position: [f32; 2],
+///     #[vertex(name = "in_color", normalized)]
+///     color: [u8; 4],
+/// }
+///
+/// assert_eq!(ColoredVertex::layout().stride, 12);
+/// ```
+///
+/// Without `#[repr(C)]` the field order is up to the compiler, so the
+/// derive rejects the struct:
+///
+/// ```compile_fail
+/// use gl_object::vertex::Vertex;
+///
+/// #[derive(Clone, Copy, Vertex)]
+/// struct ColoredVertex {
+///     in_position: [f32; 2],
+///     #[vertex(name = "in_color", normalized)]
+///     color: [u8; 4],
+/// }
+/// ```
+///
+/// # Safety
+/// The type must be `#[repr(C)]` without padding, and `layout` must
+/// describe its fields.
+pub unsafe trait Vertex: Copy {
+    fn layout() -> VertexBufferLayout;
+}
+
+/// A vertex with only a 2D position, read as `in_position`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Vertex)]
+pub struct Vertex2D {
+    pub in_position: [f32; 2],
+}
+
+/// The bytes of `vertices`, as they are uploaded.
+pub fn vertex_bytes<V: Vertex>(vertices: &[V]) -> &[u8] {
+    // Safe as `Vertex` types have no padding
+    unsafe {
+        std::slice::from_raw_parts(
+            vertices.as_ptr() as *const u8,
+            std::mem::size_of_val(vertices),
+        )
+    }
+}
+
+/// The bytes of `indices`, as they are uploaded.
+pub fn index_bytes(indices: &[u32]) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(
+            indices.as_ptr() as *const u8,
+            std::mem::size_of_val(indices),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AttributeType, Vertex, VertexAttribute, VertexBufferLayout, VertexLayout};
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Vertex)]
+    struct ColoredVertex {
+        in_position: [f32; 3],
+        #[vertex(name = "in_color", normalized)]
+        color: [u8; 4],
+        #[vertex(name = "in_uv")]
+        uv: [f32; 2],
+    }
+
+    fn attributes() -> Vec<VertexAttribute> {
+        vec![
+            VertexAttribute::f32("in_position", 3),
+            VertexAttribute::new("in_color", 4, AttributeType::U8).normalized(),
+            VertexAttribute::f32("in_uv", 2),
+        ]
+    }
+
+    fn offsets(layout: &VertexBufferLayout) -> Vec<i32> {
+        layout
+            .attributes
+            .iter()
+            .map(|attribute| attribute.offset)
+            .collect()
+    }
+
+    #[test]
+    fn packs_interleaved_attributes_back_to_back() {
+        let layout = VertexLayout::interleaved(attributes());
+
+        assert_eq!(layout.buffers.len(), 1);
+        assert_eq!(layout.buffers[0].stride, 12 + 4 + 8);
+        assert_eq!(offsets(&layout.buffers[0]), [0, 12, 16]);
+        assert_eq!(layout.buffers[0].divisor, 0);
+        assert!(layout.attributes().nth(1).unwrap().normalized);
+    }
+
+    #[test]
+    fn gives_split_attributes_a_buffer_each() {
+        let layout = VertexLayout::split(attributes());
+
+        let strides: Vec<i32> = layout.buffers.iter().map(|buffer| buffer.stride).collect();
+        assert_eq!(strides, [12, 4, 8]);
+        for buffer in layout.buffers.iter() {
+            assert_eq!(offsets(buffer), [0]);
+        }
+        let names: Vec<&str> = layout
+            .attributes()
+            .map(|attribute| attribute.name.as_str())
+            .collect();
+        assert_eq!(names, ["in_position", "in_color", "in_uv"]);
+    }
+
+    #[test]
+    fn advances_instanced_buffers_once_per_instance() {
+        let per_vertex = VertexBufferLayout::packed(vec![VertexAttribute::f32("in_position", 2)]);
+        let per_instance =
+            VertexBufferLayout::packed(vec![VertexAttribute::f32("in_model", 16)]).instanced();
+
+        assert_eq!(per_instance.divisor, 1);
+        assert_eq!(per_instance.stride, 64);
+        let layout = VertexLayout::new(vec![per_vertex, per_instance]);
+        assert_eq!(layout.buffers[0].divisor, 0);
+        assert_eq!(layout.buffers[1].divisor, 1);
+    }
+
+    #[test]
+    fn derives_the_layout_of_vertex_structs() {
+        let layout = ColoredVertex::layout();
+
+        assert_eq!(layout.stride, std::mem::size_of::<ColoredVertex>() as i32);
+        assert_eq!(layout.divisor, 0);
+        assert_eq!(layout, VertexBufferLayout::packed(attributes()));
+    }
+
+    #[test]
+    fn names_derived_attributes_after_their_field_or_rename() {
+        let names: Vec<String> = ColoredVertex::layout()
+            .attributes
+            .into_iter()
+            .map(|attribute| attribute.name)
+            .collect();
+
+        assert_eq!(names, ["in_position", "in_color", "in_uv"]);
+    }
+}
@@ -2,7 +2,14 @@ use glfw::Context;
 use glow::HasContext;
 use std::sync::mpsc::channel;
 
+use crate::camera::{Camera, CameraBuffer};
+use crate::error::Error;
 use crate::object::{OpenGLObjectTrait, TestingEvent};
+use crate::program::cache::ProgramCache;
+use crate::resource;
+use crate::scene::Scene;
+use crate::shaders::watcher::ShaderWatcher;
+use crate::shaders::ShaderSource;
 
 pub struct Window<WindowContext, WindowHandle> {
     pub width: u32,
@@ -11,6 +18,14 @@ pub struct Window<WindowContext, WindowHandle> {
     pub ctx: Option<Box<WindowContext>>,
     pub internal_handle: Option<Box<WindowHandle>>,
     pub gl: Option<Box<glow::Context>>,
+    /// Programs shared by the objects passed to `render`.
+    pub programs: ProgramCache,
+    /// Written to the `Camera` uniform block every frame and resized with
+    /// the window.
+    pub camera: Camera,
+    /// When set, programs are rebuilt between frames as their `.shader`
+    /// files change on disk.
+    pub shader_watcher: Option<ShaderWatcher>,
 
     // sdl2 specific
     #[cfg(feature = "sdl2")]
@@ -26,29 +41,88 @@ pub trait WindowTrait<WindowContext, WindowHandle> {
             ctx: None,
             internal_handle: None,
             gl: None,
+            programs: ProgramCache::new(),
+            camera: Camera::orthographic(width as f32, height as f32),
+            shader_watcher: None,
             #[cfg(feature = "sdl2")]
             gl_context: None,
         }
     }
-    fn create_display<'a>(&mut self);
-    fn render<'a>(&mut self, objects: &mut Vec<&'a mut dyn OpenGLObjectTrait>);
+    fn create_display<'a>(&mut self) -> Result<(), Error>;
+    /// Runs the render loop until the window is closed, drawing `objects`
+    /// in order. See `render_scene`.
+    fn render<'a>(
+        &mut self,
+        objects: &mut Vec<&'a mut dyn OpenGLObjectTrait>,
+    ) -> Result<(), Error> {
+        let mut scene = Scene::new();
+        for object in objects.iter_mut() {
+            scene.add_object(None, &mut **object)?;
+        }
+        self.render_scene(&mut scene)
+    }
+    /// Runs the render loop until the window is closed. Fails only if the
+    /// display is missing; objects that fail to attach or update are
+    /// reported and skipped.
+    fn render_scene(&mut self, scene: &mut Scene) -> Result<(), Error> {
+        self.render_scene_with(scene, |_| {})
+    }
+    /// Like `render_scene`, calling `on_frame` at the start of every frame,
+    /// before objects are updated. Nodes it adds are attached and drawn in
+    /// the same frame, and nodes it removes are detached.
+    fn render_scene_with<F>(&mut self, scene: &mut Scene, on_frame: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut Scene);
     fn load_with(&mut self, s: &str) -> *const std::ffi::c_void;
 }
 
-impl WindowTrait<glfw::Glfw, glfw::Window> for Window<glfw::Glfw, glfw::Window> {
-    fn render<'a>(&mut self, objects: &mut Vec<&'a mut dyn OpenGLObjectTrait>) {
-        if self.gl.is_none() {
-            panic!("gl is none");
+/// Rebuilds the cached programs whose `.shader` files changed on disk, and
+/// retries the objects of `scene` that failed with one of them. Files are
+/// watched with the defines of every permutation, and forgotten once no
+/// program or failed object uses them. A shader that fails to build is
+/// reported and the old program kept.
+fn reload_changed_shaders(
+    gl: &glow::Context,
+    watcher: &mut ShaderWatcher,
+    programs: &mut ProgramCache,
+    scene: &mut Scene,
+) {
+    let mut watched: Vec<&str> = vec![];
+    for (source, defines) in programs.permutations() {
+        if let ShaderSource::File(path) = source {
+            watcher.watch(path, defines);
+            watched.push(path);
         }
-        if self.internal_handle.is_none() {
-            panic!("internal_handle is none");
-        }
-        if self.ctx.is_none() {
-            panic!("ctx is none");
+    }
+    for (path, defines) in scene.failed_sources() {
+        watcher.watch(path, defines);
+        watched.push(path);
+    }
+    // Released programs are no longer rebuilt
+    watcher.retain(|path| watched.contains(&path));
+
+    for source in watcher.poll() {
+        scene.retry_failed(&source);
+        match unsafe { programs.reload(gl, &source) } {
+            Ok(()) => println!("Reloaded {}", source),
+            Err(error) => eprintln!("{}", error),
         }
-        let gl = self.gl.as_ref().unwrap();
-        let glfw = self.ctx.as_mut().unwrap();
-        let window = self.internal_handle.as_mut().unwrap();
+    }
+}
+
+impl WindowTrait<glfw::Glfw, glfw::Window> for Window<glfw::Glfw, glfw::Window> {
+    fn render_scene_with<F>(&mut self, scene: &mut Scene, mut on_frame: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut Scene),
+    {
+        let (gl, glfw, window) = match (
+            self.gl.as_ref(),
+            self.ctx.as_mut(),
+            self.internal_handle.as_mut(),
+        ) {
+            (Some(gl), Some(glfw), Some(window)) => (gl, glfw, window),
+            _ => return Err(Error::NoDisplay),
+        };
         let (sender, receiver): (
             std::sync::mpsc::Sender<(f64, glfw::WindowEvent)>,
             std::sync::mpsc::Receiver<(f64, glfw::WindowEvent)>,
@@ -61,11 +135,17 @@ impl WindowTrait<glfw::Glfw, glfw::Window> for Window<glfw::Glfw, glfw::Window>
             );
         }
 
+        let mut camera_buffer = unsafe { CameraBuffer::new(gl)? };
+
         while !window.should_close() {
             glfw.poll_events();
+            if let Some(watcher) = self.shader_watcher.as_mut() {
+                reload_changed_shaders(gl, watcher, &mut self.programs, scene);
+            }
             let mut test_event = None;
             for (_, event) in glfw::flush_messages(&receiver) {
                 if let glfw::WindowEvent::Size(x, y) = event {
+                    self.camera.resize(x as f32, y as f32);
                     test_event = Some(TestingEvent::new(
                         x,
                         y,
@@ -78,19 +158,13 @@ impl WindowTrait<glfw::Glfw, glfw::Window> for Window<glfw::Glfw, glfw::Window>
             unsafe {
                 gl.clear_color(0.1, 0.2, 0.3, 1.0);
                 gl.clear(glow::COLOR_BUFFER_BIT);
+                camera_buffer.upload(gl, &self.camera);
             }
-            for elem in objects.into_iter() {
-                elem.attach(gl);
-
-                if test_event.is_some() {
-                    let sizes = test_event.as_ref().unwrap();
-                    elem.window_resize(
-                        [sizes.window_draw_resize[0], sizes.window_draw_resize[1]],
-                        [sizes.window_resize[0], sizes.window_resize[1]],
-                    )
-                }
-                elem.render(gl);
-            }
+            on_frame(scene);
+            // Attaches new objects; every object is detached after the loop
+            scene.step(gl, &mut self.programs, test_event.as_ref());
+            scene.render(gl);
+            self.programs.report_uniforms();
 
             let (x, y) = window.get_framebuffer_size();
             unsafe {
@@ -98,13 +172,20 @@ impl WindowTrait<glfw::Glfw, glfw::Window> for Window<glfw::Glfw, glfw::Window>
             }
             window.swap_buffers();
         }
-        for elem in objects.into_iter() {
-            elem.detach(gl);
+        scene.detach(gl, &mut self.programs);
+        unsafe {
+            camera_buffer.delete(gl);
+            self.programs.clear(gl);
+        }
+        if cfg!(debug_assertions) {
+            resource::report_leaks();
         }
+        Ok(())
     }
 
-    fn create_display<'a>(&mut self) {
-        let mut glfw: glfw::Glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
+    fn create_display<'a>(&mut self) -> Result<(), Error> {
+        let mut glfw: glfw::Glfw =
+            glfw::init(glfw::LOG_ERRORS).map_err(|error| Error::Display(error.to_string()))?;
 
         glfw.window_hint(glfw::WindowHint::ContextVersionMajor(4));
         glfw.window_hint(glfw::WindowHint::ContextVersionMinor(1));
@@ -121,7 +202,7 @@ impl WindowTrait<glfw::Glfw, glfw::Window> for Window<glfw::Glfw, glfw::Window>
                 &self.title,
                 glfw::WindowMode::Windowed,
             )
-            .expect("Failed to create GLFW window.");
+            .ok_or_else(|| Error::Display("cannot create GLFW window".to_string()))?;
 
         window.set_framebuffer_size_polling(true);
         window.set_size_polling(true);
@@ -143,6 +224,7 @@ impl WindowTrait<glfw::Glfw, glfw::Window> for Window<glfw::Glfw, glfw::Window>
         self.gl = Some(Box::new(gl));
 
         // self.render(objects);
+        Ok(())
     }
 
     fn load_with(&mut self, s: &str) -> *const std::ffi::c_void {
@@ -152,10 +234,10 @@ impl WindowTrait<glfw::Glfw, glfw::Window> for Window<glfw::Glfw, glfw::Window>
 
 #[cfg(feature = "sdl2")]
 impl WindowTrait<sdl2::Sdl, sdl2::video::Window> for Window<sdl2::Sdl, sdl2::video::Window> {
-    fn create_display<'a>(&mut self) {
-        let ctx = sdl2::init().unwrap();
+    fn create_display<'a>(&mut self) -> Result<(), Error> {
+        let ctx = sdl2::init().map_err(Error::Display)?;
 
-        let video_subsystem = ctx.video().unwrap();
+        let video_subsystem = ctx.video().map_err(Error::Display)?;
 
         let gl_attr = video_subsystem.gl_attr();
 
@@ -170,24 +252,30 @@ impl WindowTrait<sdl2::Sdl, sdl2::video::Window> for Window<sdl2::Sdl, sdl2::vid
             .position_centered()
             .resizable()
             .build()
-            .unwrap();
+            .map_err(|error| Error::Display(error.to_string()))?;
 
-        let gl_context = window.gl_create_context().unwrap();
+        let gl_context = window.gl_create_context().map_err(Error::Display)?;
         let gl = unsafe {
             glow::Context::from_loader_function(|s| window.subsystem().gl_get_proc_address(s) as _)
         };
 
         println!("SDL {:?}", gl.version());
 
-        window.gl_make_current(&gl_context).unwrap();
+        window
+            .gl_make_current(&gl_context)
+            .map_err(Error::Display)?;
 
-        window.subsystem().gl_set_swap_interval(1).unwrap();
+        window
+            .subsystem()
+            .gl_set_swap_interval(1)
+            .map_err(Error::Display)?;
         self.gl_context = Some(Box::new(gl_context));
 
         self.ctx = Some(Box::new(ctx));
         self.internal_handle = Some(Box::new(window));
         self.gl = Some(Box::new(gl));
         // self.render(objects);
+        Ok(())
     }
 
     fn load_with(&mut self, s: &str) -> *const std::ffi::c_void {
@@ -197,26 +285,23 @@ impl WindowTrait<sdl2::Sdl, sdl2::video::Window> for Window<sdl2::Sdl, sdl2::vid
     }
 
     // calling externally on SDL2 fails.
-    fn render(&mut self, objects: &mut Vec<&mut dyn OpenGLObjectTrait>) {
-        if self.gl.is_none() {
-            panic!("gl is none");
-        }
-        if self.internal_handle.is_none() {
-            panic!("internal_handle is none");
-        }
-        if self.ctx.is_none() {
-            panic!("ctx is none");
-        }
+    fn render_scene_with<F>(&mut self, scene: &mut Scene, mut on_frame: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut Scene),
+    {
+        let (gl, ctx, window, gl_context) = match (
+            self.gl.as_ref(),
+            self.ctx.as_ref(),
+            self.internal_handle.as_ref(),
+            self.gl_context.as_ref(),
+        ) {
+            (Some(gl), Some(ctx), Some(window), Some(gl_context)) => (gl, ctx, window, gl_context),
+            _ => return Err(Error::NoDisplay),
+        };
         unsafe {
-            let gl = self.gl.as_ref().unwrap();
-            let ctx = self.ctx.as_ref().unwrap();
-            let window = self.internal_handle.as_ref().unwrap();
-
-            window
-                .gl_make_current(&self.gl_context.as_ref().unwrap())
-                .unwrap();
+            window.gl_make_current(gl_context).map_err(Error::Display)?;
 
-            let mut event_pump = ctx.event_pump().unwrap();
+            let mut event_pump = ctx.event_pump().map_err(Error::Display)?;
 
             gl.clear_color(0.1, 0.2, 0.3, 1.0);
             gl.viewport(
@@ -226,12 +311,15 @@ impl WindowTrait<sdl2::Sdl, sdl2::video::Window> for Window<sdl2::Sdl, sdl2::vid
                 window.drawable_size().1 as i32,
             );
 
+            let mut camera_buffer = CameraBuffer::new(gl)?;
+
             'render: loop {
                 let mut test_event = None;
                 {
                     for event in event_pump.poll_iter() {
                         if let sdl2::event::Event::Window { win_event, .. } = event {
                             if let sdl2::event::WindowEvent::Resized(x, y) = win_event {
+                                self.camera.resize(x as f32, y as f32);
                                 gl.viewport(
                                     0,
                                     0,
@@ -253,27 +341,28 @@ impl WindowTrait<sdl2::Sdl, sdl2::video::Window> for Window<sdl2::Sdl, sdl2::vid
                     }
                 }
 
-                gl.clear(glow::COLOR_BUFFER_BIT);
-
-                for elem in objects.into_iter() {
-                    elem.attach(gl);
+                if let Some(watcher) = self.shader_watcher.as_mut() {
+                    reload_changed_shaders(gl, watcher, &mut self.programs, scene);
+                }
 
-                    if test_event.is_some() {
-                        let sizes = test_event.as_ref().unwrap();
-                        elem.window_resize(
-                            [sizes.window_draw_resize[0], sizes.window_draw_resize[1]],
-                            [sizes.window_resize[0], sizes.window_resize[1]],
-                        )
-                    }
+                gl.clear(glow::COLOR_BUFFER_BIT);
+                camera_buffer.upload(gl, &self.camera);
 
-                    elem.render(gl);
-                }
+                on_frame(scene);
+                // Attaches new objects; every object is detached after the loop
+                scene.step(gl, &mut self.programs, test_event.as_ref());
+                scene.render(gl);
+                self.programs.report_uniforms();
 
                 window.gl_swap_window();
             }
-            for elem in objects.into_iter() {
-                elem.detach(gl);
+            scene.detach(gl, &mut self.programs);
+            camera_buffer.delete(gl);
+            self.programs.clear(gl);
+            if cfg!(debug_assertions) {
+                resource::report_leaks();
             }
         }
+        Ok(())
     }
 }